@@ -136,7 +136,8 @@ fn getRGBA(color: u32) -> vec4<u32> {
 
 
 fn mul_and_clamp_to_255(val: f32) -> u32 {
-    return clamp(u32(val * 255.0), u32(0), u32(255));
+    // Clamp before converting, float to u32 of negative values is undefined on some backends.
+    return u32(clamp(val, 0.0, 1.0) * 255.0);
 }
 
 fn to_color32(color: vec4<f32>) -> u32 {
@@ -155,8 +156,17 @@ fn to_color32(color: vec4<f32>) -> u32 {
 
 struct NoiseInput {
     seed: f32,
+    frequency: f32,
     offset: vec2<f32>,
     size: vec2<u32>,
+    octaves: u32,
+    // 0 fbm, 1 ridged, 2 billow
+    mode: u32,
+    lacunarity: f32,
+    persistence: f32,
+    amplitude: f32,
+    // Pixels per row of outputBuffer, rows are padded past the width.
+    row_length: u32,
 }
 @group(0) @binding(0)
 var<uniform> noise_input: NoiseInput;
@@ -165,24 +175,53 @@ var<uniform> noise_input: NoiseInput;
 var<storage, read_write> outputBuffer: array<u32>; 
 
 
+// Sums octaves of snoise, scaling the xy frequency by lacunarity and the
+// amplitude by persistence each octave. Kept in step with `noise::cpu::fractal`.
+fn fractal(P: vec3f) -> f32 {
+    var sum = 0.0;
+    var amplitude = noise_input.amplitude;
+    var frequency = 1.0;
+    for (var i = 0u; i < noise_input.octaves; i++) {
+        let n = snoise(vec3f(P.xy * frequency, P.z));
+        switch noise_input.mode {
+            case 1u: {
+                sum += (1.0 - abs(n)) * (1.0 - abs(n)) * amplitude;
+            }
+            case 2u: {
+                sum += (abs(n) * 2.0 - 1.0) * amplitude;
+            }
+            default: {
+                sum += n * amplitude;
+            }
+        }
+        frequency *= noise_input.lacunarity;
+        amplitude *= noise_input.persistence;
+    }
+    return sum;
+}
+
 @compute @workgroup_size(64)
 fn cm_main(
     @builtin(global_invocation_id) global_id: vec3<u32>,
 ) {
-    let frequency = 5.0;
+    let frequency = noise_input.frequency;
     let colorMultiplier = 5.5;
     let colorAdditive = 0.5;
     let pixelCoord = vec2<u32>(global_id.x, global_id.y);
+    let width = noise_input.size.x;
+    let height = noise_input.size.y;
+
+    // The dispatch covers whole padded rows, skip the pixels past the edge.
+    if (pixelCoord.x >= width || pixelCoord.y >= height) {
+        return;
+    }
+
     let offset = noise_input.offset;
     var normalizedCoord = vec2f(pixelCoord) / vec2f(noise_input.size);
 
-    var noise = snoise(vec3f(normalizedCoord * frequency + offset.yx, noise_input.seed * 10000.));
+    var noise = fractal(vec3f(normalizedCoord * frequency + offset.yx, noise_input.seed * 10000.));
     var color = vec4f(vec3f(noise * colorMultiplier + colorAdditive), 1.0);
 
-    let width = noise_input.size.x;
-    let height = noise_input.size.y;
-
-    let index = pixelCoord.x * width + (height - pixelCoord.y);
-    // outputBuffer[index] = to_color32(color);
-    outputBuffer[index] = to_color32(vec4f(vec2f(normalizedCoord.xy), 1.0, 1.0));
+    let index = pixelCoord.y * noise_input.row_length + pixelCoord.x;
+    outputBuffer[index] = to_color32(color);
 }
@@ -0,0 +1,74 @@
+
+
+struct CameraUniform {
+    view_pos: vec4<f32>,
+    view_proj: mat4x4<f32>,
+}
+
+@group(1) @binding(0)
+var<uniform> camera: CameraUniform;
+
+
+struct Light {
+    color: vec3<f32>,
+}
+
+
+@group(2) @binding(0)
+var<uniform> light: Light;
+
+
+struct VertexInput {
+    @location(0) position: vec3<f32>,
+    @location(1) tex_coords: vec2<f32>,
+    @location(2) normal: vec3<f32>,
+    @location(3) layer: u32,
+};
+
+
+struct VertexOutput {
+    @builtin(position) clip_position: vec4<f32>,
+    @location(0) tex_coords: vec2<f32>,
+    @location(1) normal: vec3<f32>,
+    @location(2) @interpolate(flat) layer: u32,
+};
+
+
+@vertex
+fn vs_main(
+    model: VertexInput,
+) -> VertexOutput {
+    var out: VertexOutput;
+    out.tex_coords = model.tex_coords;
+    out.normal = model.normal;
+    out.layer = model.layer;
+    out.clip_position = camera.view_proj * vec4<f32>(model.position, 1.0);
+    return out;
+}
+
+
+
+@group(0) @binding(0)
+var t_blocks: texture_2d_array<f32>;
+@group(0) @binding(1)
+var s_blocks: sampler;
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+
+    let object_color = textureSample(t_blocks, s_blocks, in.tex_coords, in.layer);
+
+    // Fixed sun direction, faces pointing away from it are only lit by ambient.
+    let sun_direction = normalize(vec3<f32>(0.4, 1.0, 0.3));
+    let ambient_strength = 0.45;
+    let diffuse_strength = max(dot(normalize(in.normal), sun_direction), 0.0) * 0.55;
+
+    let result = light.color * (ambient_strength + diffuse_strength) * object_color.xyz;
+
+    return vec4<f32>(result, object_color.a);
+}
+
+@fragment
+fn fs_main_wf(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(abs(in.normal), 1.0);
+}
@@ -1,17 +1,491 @@
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
-#[derive(serde::Deserialize, serde::Serialize)]
+use anyhow::Context;
+use clap::{Args, ValueEnum};
+use log::{error, info, warn};
+use winit::keyboard::KeyCode;
+
+use crate::noise::FractalSettings;
+
+/// Bumped whenever a field moves or changes meaning, older files are migrated
+/// by `AppConfig::migrate` before parsing.
+pub const CONFIG_VERSION: u64 = 1;
+
+const CONFIG_FILE_NAME: &str = "config.json";
+const AUTOEXEC_FILE_NAME: &str = "autoexec.cfg";
+const CONSOLE_HISTORY_FILE_NAME: &str = "console_history.txt";
+
+/// Engine settings file. Missing fields fall back to their defaults.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
 pub struct AppConfig {
-    font_size: u16,
+    pub version: u64,
+    pub window: WindowConfig,
+    pub graphics: GraphicsConfig,
+    pub camera: CameraConfig,
+    pub ui: UiConfig,
+    pub key_bindings: KeyBindings,
+    pub noise: NoiseConfig,
+}
+
+impl Default for AppConfig {
+    fn default() -> Self {
+        Self {
+            version: CONFIG_VERSION,
+            window: WindowConfig::default(),
+            graphics: GraphicsConfig::default(),
+            camera: CameraConfig::default(),
+            ui: UiConfig::default(),
+            key_bindings: KeyBindings::default(),
+            noise: NoiseConfig::default(),
+        }
+    }
 }
 
 impl AppConfig {
     pub fn from_json(json: &str) -> Result<Self, serde_json::Error> {
-        serde_json::from_str(json)
+        let mut value: serde_json::Value = serde_json::from_str(json)?;
+        Self::migrate(&mut value);
+        serde_json::from_value(value)
+    }
+
+    /// Upgrades the json of an older config file to `CONFIG_VERSION`, files
+    /// written before versioning count as version 0.
+    fn migrate(value: &mut serde_json::Value) {
+        let Some(object) = value.as_object_mut() else {
+            return;
+        };
+        let version = object
+            .get("version")
+            .and_then(serde_json::Value::as_u64)
+            .unwrap_or(0);
+        if version > CONFIG_VERSION {
+            warn!(
+                "Config version {version} is newer than {CONFIG_VERSION}, unknown fields are ignored and it won't be saved over"
+            );
+            return;
+        }
+
+        if version < 1 {
+            // Version 0 only had the font size, at the top level.
+            if let Some(font_size) = object.remove("font_size") {
+                object.insert("ui".into(), serde_json::json!({ "font_size": font_size }));
+            }
+        }
+
+        if version != CONFIG_VERSION {
+            info!("Migrated config from version {version} to {CONFIG_VERSION}");
+        }
+        object.insert("version".into(), CONFIG_VERSION.into());
+    }
+
+    /// Config in the platform config directory, `None` if the platform has none.
+    pub fn default_path() -> Option<PathBuf> {
+        let config_dir = if cfg!(target_os = "windows") {
+            PathBuf::from(std::env::var_os("APPDATA")?)
+        } else if cfg!(target_os = "macos") {
+            PathBuf::from(std::env::var_os("HOME")?).join("Library/Application Support")
+        } else {
+            match std::env::var_os("XDG_CONFIG_HOME") {
+                Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+                _ => PathBuf::from(std::env::var_os("HOME")?).join(".config"),
+            }
+        };
+
+        Some(config_dir.join("lotus").join(CONFIG_FILE_NAME))
+    }
+
+    /// Loads the config at `path`, defaults if it doesn't exist yet.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let json = match std::fs::read_to_string(path) {
+            Ok(json) => json,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                info!("No config at {:?}, using defaults", path);
+                return Ok(Self::default());
+            }
+            Err(err) => return Err(err).with_context(|| format!("Failed to read {path:?}")),
+        };
+
+        Self::from_json(&json).with_context(|| format!("Failed to parse {path:?}"))
     }
 
-    pub async fn write_json_file(&self, path: &Path) -> anyhow::Result<()> {
-        tokio::fs::write(path, serde_json::to_string(&self)?.as_bytes()).await?;
+    /// Writes the config to `path`, unless it was loaded from a newer version
+    /// whose fields saving would drop.
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if self.version > CONFIG_VERSION {
+            anyhow::bail!(
+                "Not saving over {path:?}, its version {} is newer than {CONFIG_VERSION}",
+                self.version
+            );
+        }
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(&self)?)
+            .with_context(|| format!("Failed to write {path:?}"))?;
+
         Ok(())
     }
 }
+
+/// Loaded config and the file it gets saved back to, `None` keeps changes in
+/// memory only.
+#[derive(Debug, Clone, Default)]
+pub struct ConfigFile {
+    pub config: AppConfig,
+    pub path: Option<PathBuf>,
+    /// Set by `edit`, the config is saved at the end of the frame.
+    pub changed: bool,
+}
+
+impl ConfigFile {
+    /// Script run at startup, next to the config file.
+    pub fn autoexec_path(&self) -> Option<PathBuf> {
+        let path = self.path.as_ref()?;
+        Some(path.with_file_name(AUTOEXEC_FILE_NAME))
+    }
+
+    /// Commands submitted in the console, next to the config file.
+    pub fn console_history_path(&self) -> Option<PathBuf> {
+        let path = self.path.as_ref()?;
+        Some(path.with_file_name(CONSOLE_HISTORY_FILE_NAME))
+    }
+
+    /// Changes a saved setting, `save_if_changed` writes it later.
+    pub fn edit(&mut self, edit: impl FnOnce(&mut AppConfig)) {
+        edit(&mut self.config);
+        self.changed = true;
+    }
+
+    /// Writes the config to its file if it has one and it changed.
+    pub fn save_if_changed(&mut self) {
+        if !std::mem::take(&mut self.changed) {
+            return;
+        }
+        if let Some(path) = &self.path {
+            if let Err(err) = self.config.save(path) {
+                error!("{err:#}");
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct WindowConfig {
+    pub width: u32,
+    pub height: u32,
+    pub fullscreen: bool,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        Self {
+            width: 1280,
+            height: 720,
+            fullscreen: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct CameraConfig {
+    /// Vertical field of view in degrees.
+    pub fov: f32,
+    /// Blocks per second.
+    pub speed: f32,
+    pub sensitivity: f32,
+}
+
+impl Default for CameraConfig {
+    fn default() -> Self {
+        Self {
+            fov: 45.0,
+            speed: 4.0,
+            sensitivity: 0.4,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct UiConfig {
+    pub font_size: u16,
+    pub show_fps: bool,
+    pub show_wireframe: bool,
+}
+
+impl Default for UiConfig {
+    fn default() -> Self {
+        Self {
+            font_size: 14,
+            show_fps: false,
+            show_wireframe: false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum KeyAction {
+    Forward,
+    Backward,
+    Left,
+    Right,
+    Up,
+    Down,
+    ToggleSettings,
+    OpenConsole,
+    ReloadNoise,
+}
+
+/// Keys bound to each action, an action can have several.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct KeyBindings {
+    pub forward: Vec<KeyCode>,
+    pub backward: Vec<KeyCode>,
+    pub left: Vec<KeyCode>,
+    pub right: Vec<KeyCode>,
+    pub up: Vec<KeyCode>,
+    pub down: Vec<KeyCode>,
+    pub toggle_settings: Vec<KeyCode>,
+    pub open_console: Vec<KeyCode>,
+    pub reload_noise: Vec<KeyCode>,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        Self {
+            forward: vec![KeyCode::KeyW, KeyCode::ArrowUp],
+            backward: vec![KeyCode::KeyS, KeyCode::ArrowDown],
+            left: vec![KeyCode::KeyA, KeyCode::ArrowLeft],
+            right: vec![KeyCode::KeyD, KeyCode::ArrowRight],
+            up: vec![KeyCode::Space],
+            down: vec![KeyCode::ShiftLeft],
+            toggle_settings: vec![KeyCode::Escape],
+            open_console: vec![KeyCode::Backquote],
+            reload_noise: vec![KeyCode::KeyR],
+        }
+    }
+}
+
+impl KeyBindings {
+    pub fn action(&self, key_code: KeyCode) -> Option<KeyAction> {
+        [
+            (&self.forward, KeyAction::Forward),
+            (&self.backward, KeyAction::Backward),
+            (&self.left, KeyAction::Left),
+            (&self.right, KeyAction::Right),
+            (&self.up, KeyAction::Up),
+            (&self.down, KeyAction::Down),
+            (&self.toggle_settings, KeyAction::ToggleSettings),
+            (&self.open_console, KeyAction::OpenConsole),
+            (&self.reload_noise, KeyAction::ReloadNoise),
+        ]
+        .into_iter()
+        .find(|(keys, _)| keys.contains(&key_code))
+        .map(|(_, action)| action)
+    }
+}
+
+/// Starting values of the noise generator.
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct NoiseConfig {
+    pub size: (u32, u32),
+    pub frequency: f32,
+    pub fractal: FractalSettings,
+    /// Where the settings ui saves the noise texture.
+    pub save_path: String,
+}
+
+impl Default for NoiseConfig {
+    fn default() -> Self {
+        Self {
+            size: (1024, 1024),
+            frequency: 5.0,
+            fractal: FractalSettings::default(),
+            save_path: "noise.png".into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, serde::Deserialize, serde::Serialize)]
+#[serde(default)]
+pub struct GraphicsConfig {
+    pub backend: GraphicsBackend,
+    pub power_preference: PowerPreference,
+    /// Only consider software adapters.
+    pub force_fallback_adapter: bool,
+    pub present_mode: PresentMode,
+    /// Radius in chunks of the terrain around the camera.
+    pub render_distance: u32,
+}
+
+impl Default for GraphicsConfig {
+    fn default() -> Self {
+        Self {
+            backend: GraphicsBackend::default(),
+            power_preference: PowerPreference::default(),
+            force_fallback_adapter: false,
+            present_mode: PresentMode::default(),
+            render_distance: 3,
+        }
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize, ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum GraphicsBackend {
+    /// Whichever backend has the best adapter.
+    #[default]
+    Auto,
+    Vulkan,
+    Metal,
+    Dx12,
+    Gl,
+}
+
+impl GraphicsBackend {
+    pub fn backends(&self) -> wgpu::Backends {
+        match self {
+            GraphicsBackend::Auto => wgpu::Backends::all(),
+            GraphicsBackend::Vulkan => wgpu::Backends::VULKAN,
+            GraphicsBackend::Metal => wgpu::Backends::METAL,
+            GraphicsBackend::Dx12 => wgpu::Backends::DX12,
+            GraphicsBackend::Gl => wgpu::Backends::GL,
+        }
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize, ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum PowerPreference {
+    #[default]
+    None,
+    LowPower,
+    HighPerformance,
+}
+
+impl From<PowerPreference> for wgpu::PowerPreference {
+    fn from(value: PowerPreference) -> Self {
+        match value {
+            PowerPreference::None => wgpu::PowerPreference::None,
+            PowerPreference::LowPower => wgpu::PowerPreference::LowPower,
+            PowerPreference::HighPerformance => wgpu::PowerPreference::HighPerformance,
+        }
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, Default, PartialEq, Eq, serde::Deserialize, serde::Serialize, ValueEnum,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum PresentMode {
+    #[default]
+    AutoVsync,
+    AutoNoVsync,
+    Fifo,
+    FifoRelaxed,
+    Immediate,
+    Mailbox,
+}
+
+impl From<PresentMode> for wgpu::PresentMode {
+    fn from(value: PresentMode) -> Self {
+        match value {
+            PresentMode::AutoVsync => wgpu::PresentMode::AutoVsync,
+            PresentMode::AutoNoVsync => wgpu::PresentMode::AutoNoVsync,
+            PresentMode::Fifo => wgpu::PresentMode::Fifo,
+            PresentMode::FifoRelaxed => wgpu::PresentMode::FifoRelaxed,
+            PresentMode::Immediate => wgpu::PresentMode::Immediate,
+            PresentMode::Mailbox => wgpu::PresentMode::Mailbox,
+        }
+    }
+}
+
+/// Command line overrides of `GraphicsConfig`.
+#[derive(Args, Debug, Default)]
+pub struct GraphicsArgs {
+    /// Graphics API to render with.
+    #[clap(long, value_enum)]
+    backend: Option<GraphicsBackend>,
+    /// Prefer integrated or discrete GPUs.
+    #[clap(long, value_enum)]
+    power_preference: Option<PowerPreference>,
+    /// Only consider software adapters.
+    #[clap(long)]
+    force_fallback_adapter: bool,
+    /// Vsync behaviour of the window.
+    #[clap(long, value_enum)]
+    present_mode: Option<PresentMode>,
+}
+
+impl GraphicsArgs {
+    pub fn apply(&self, config: &mut GraphicsConfig) {
+        config.backend = self.backend.unwrap_or(config.backend);
+        config.power_preference = self.power_preference.unwrap_or(config.power_preference);
+        config.force_fallback_adapter |= self.force_fallback_adapter;
+        config.present_mode = self.present_mode.unwrap_or(config.present_mode);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moves_the_version_0_font_size_into_ui() {
+        let config =
+            AppConfig::from_json(r#"{ "font_size": 20, "camera": { "fov": 60.0 } }"#).unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.ui.font_size, 20);
+        assert_eq!(config.camera.fov, 60.0);
+        assert_eq!(config.ui.show_fps, UiConfig::default().show_fps);
+    }
+
+    #[test]
+    fn keeps_version_1_fields_in_place() {
+        let json = r#"{ "version": 1, "font_size": 20, "ui": { "font_size": 18 } }"#;
+        assert_eq!(AppConfig::from_json(json).unwrap().ui.font_size, 18);
+    }
+
+    #[test]
+    fn saves_and_loads_the_same_config() {
+        let path = std::env::temp_dir()
+            .join(format!("lotus-config-{}", std::process::id()))
+            .join(CONFIG_FILE_NAME);
+        let mut config = AppConfig::default();
+        config.ui.font_size = 17;
+        config.camera.speed = 9.5;
+        config.key_bindings.forward = vec![KeyCode::KeyI];
+        config.noise.save_path = "saved noise.png".into();
+        config.graphics.backend = GraphicsBackend::Gl;
+
+        config.save(&path).unwrap();
+        let loaded = AppConfig::load(&path);
+        std::fs::remove_dir_all(path.parent().unwrap()).unwrap();
+        assert_eq!(loaded.unwrap(), config);
+    }
+
+    #[test]
+    fn wont_save_over_newer_versions() {
+        let json = format!(
+            r#"{{ "version": {}, "new_field": true }}"#,
+            CONFIG_VERSION + 1
+        );
+        let config = AppConfig::from_json(&json).unwrap();
+        assert_eq!(config.version, CONFIG_VERSION + 1);
+
+        let path =
+            std::env::temp_dir().join(format!("lotus-newer-config-{}.json", std::process::id()));
+        let err = config.save(&path).unwrap_err();
+        assert!(err.to_string().contains("is newer than"), "{err}");
+        assert!(!path.exists());
+    }
+}
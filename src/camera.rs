@@ -1,19 +1,22 @@
 use std::{f32::consts::FRAC_PI_2, time::Duration};
 
-use cgmath::{perspective, InnerSpace, Matrix4, Point3, Rad, Vector3};
+use cgmath::{perspective, InnerSpace, Matrix, Matrix4, Point3, Rad, Vector3, Vector4};
 use log::warn;
 use winit::{
     dpi::PhysicalPosition,
     event::{ElementState, KeyEvent, MouseScrollDelta},
-    keyboard::KeyCode,
 };
 
+use crate::app_config::KeyAction;
+
+/// Maps OpenGL's -1..1 clip depth to wgpu's 0..1, `Matrix4::new` takes
+/// columns so the 0.5 offset of z is in the last one.
 #[rustfmt::skip]
 pub const OPENGL_TO_WGPU_MATRIX: cgmath::Matrix4<f32> = cgmath::Matrix4::new(
     1.0, 0.0, 0.0, 0.0,
     0.0, 1.0, 0.0, 0.0,
-    0.0, 0.0, 0.5, 0.5,
-    0.0, 0.0, 0.0, 1.0,
+    0.0, 0.0, 0.5, 0.0,
+    0.0, 0.0, 0.5, 1.0,
 );
 
 pub const SAFE_FRAC_PI_2: f32 = FRAC_PI_2 - 0.0001;
@@ -38,6 +41,10 @@ impl Projection {
         self.aspect = width as f32 / height as f32;
     }
 
+    pub fn set_fovy(&mut self, fovy: Rad<f32>) {
+        self.fovy = fovy;
+    }
+
     pub fn calc_matrix(&self) -> Matrix4<f32> {
         OPENGL_TO_WGPU_MATRIX * perspective(self.fovy, self.aspect, self.znear, self.zfar)
     }
@@ -70,6 +77,37 @@ impl Camera {
     }
 }
 
+pub struct Frustum {
+    planes: [Vector4<f32>; 6],
+}
+
+impl Frustum {
+    /// Extracts the clip planes of a view projection matrix with wgpu's 0..1
+    /// depth range, plane normals point inwards.
+    pub fn from_matrix(view_proj: Matrix4<f32>) -> Self {
+        let [r0, r1, r2, r3] = [0, 1, 2, 3].map(|i| view_proj.row(i));
+        let planes = [r3 + r0, r3 - r0, r3 + r1, r3 - r1, r2, r3 - r2]
+            .map(|plane| plane / plane.truncate().magnitude());
+
+        Self { planes }
+    }
+
+    pub fn from_camera(camera: &Camera, projection: &Projection) -> Self {
+        Self::from_matrix(projection.calc_matrix() * camera.calc_matrix())
+    }
+
+    pub fn intersects_aabb(&self, min: Vector3<f32>, max: Vector3<f32>) -> bool {
+        self.planes.iter().all(|plane| {
+            let corner = Vector3::new(
+                if plane.x >= 0.0 { max.x } else { min.x },
+                if plane.y >= 0.0 { max.y } else { min.y },
+                if plane.z >= 0.0 { max.z } else { min.z },
+            );
+            plane.truncate().dot(corner) + plane.w >= 0.0
+        })
+    }
+}
+
 #[repr(C)]
 #[derive(Debug, Copy, Clone, bytemuck::Pod, bytemuck::Zeroable)]
 pub struct CameraUniform {
@@ -116,16 +154,23 @@ impl CameraController {
         }
     }
 
-    pub fn process_keyboard(&mut self, key_code: KeyCode, is_pressed: bool) -> bool {
-        // let is_pressed = event.state == ElementState::Pressed;
+    pub fn set_speed(&mut self, speed: f32) {
+        self.speed = speed;
+    }
+
+    pub fn set_sensitivity(&mut self, sensitivity: f32) {
+        self.sensitivity = sensitivity;
+    }
+
+    pub fn process_action(&mut self, action: KeyAction, is_pressed: bool) -> bool {
         let amount = if is_pressed { 1.0 } else { 0.0 };
-        match key_code {
-            KeyCode::KeyA | KeyCode::ArrowLeft => self.amount_left = amount,
-            KeyCode::KeyD | KeyCode::ArrowRight => self.amount_right = amount,
-            KeyCode::KeyW | KeyCode::ArrowUp => self.amount_forward = amount,
-            KeyCode::KeyS | KeyCode::ArrowDown => self.amount_backward = amount,
-            KeyCode::Space => self.amount_up = amount,
-            KeyCode::ShiftLeft => self.amount_down = amount,
+        match action {
+            KeyAction::Left => self.amount_left = amount,
+            KeyAction::Right => self.amount_right = amount,
+            KeyAction::Forward => self.amount_forward = amount,
+            KeyAction::Backward => self.amount_backward = amount,
+            KeyAction::Up => self.amount_up = amount,
+            KeyAction::Down => self.amount_down = amount,
             _ => return false,
         };
 
@@ -175,3 +220,52 @@ impl CameraController {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use cgmath::Deg;
+
+    use super::*;
+
+    /// Looking down +x from the origin, with the far plane at 100.
+    fn frustum() -> Frustum {
+        let camera = Camera::new(Point3::new(0.0, 0.0, 0.0), Rad(0.0), Rad(0.0));
+        let projection = Projection::new(800, 600, Deg(45.0).into(), 0.1, 100.0);
+        Frustum::from_camera(&camera, &projection)
+    }
+
+    fn intersects(frustum: &Frustum, min: [f32; 3], max: [f32; 3]) -> bool {
+        frustum.intersects_aabb(min.into(), max.into())
+    }
+
+    #[test]
+    fn keeps_boxes_in_front_of_the_camera() {
+        let frustum = frustum();
+        assert!(intersects(&frustum, [10.0, -1.0, -1.0], [12.0, 1.0, 1.0]));
+        // Only partly inside, across the far plane and the near plane.
+        assert!(intersects(&frustum, [99.0, -1.0, -1.0], [101.0, 1.0, 1.0]));
+        assert!(intersects(&frustum, [-1.0, -1.0, -1.0], [1.0, 1.0, 1.0]));
+    }
+
+    #[test]
+    fn culls_boxes_behind_the_camera_and_past_the_far_plane() {
+        let frustum = frustum();
+        assert!(!intersects(
+            &frustum,
+            [-12.0, -1.0, -1.0],
+            [-10.0, 1.0, 1.0]
+        ));
+        assert!(!intersects(
+            &frustum,
+            [150.0, -1.0, -1.0],
+            [152.0, 1.0, 1.0]
+        ));
+    }
+
+    #[test]
+    fn culls_boxes_beside_the_view() {
+        let frustum = frustum();
+        assert!(!intersects(&frustum, [10.0, -1.0, 50.0], [12.0, 1.0, 52.0]));
+        assert!(!intersects(&frustum, [10.0, 50.0, -1.0], [12.0, 52.0, 1.0]));
+    }
+}
@@ -0,0 +1,48 @@
+use std::path::PathBuf;
+
+use clap::Parser;
+
+use crate::{app_config::GraphicsArgs, remote_console::ListenAddress, script::OnError};
+
+/// Command based voxel game engine.
+#[derive(Parser, Debug)]
+#[clap(name = "lotus")]
+pub struct Cli {
+    /// Config file to load and save settings to, instead of the one in the
+    /// platform config directory.
+    #[clap(long)]
+    pub config: Option<PathBuf>,
+    /// World file with the terrain settings, created with a new seed if
+    /// it doesn't exist.
+    #[clap(long)]
+    pub world: Option<PathBuf>,
+    /// Terrain seed, overrides the one in `--world`.
+    #[clap(long)]
+    pub seed: Option<f32>,
+    /// Render a single frame without a window and save it to `--output`.
+    #[clap(long)]
+    pub headless: bool,
+    /// Where `--headless` saves the frame.
+    #[clap(long, default_value = "frame.png")]
+    pub output: PathBuf,
+    /// Console commands to run once the engine has started, one per line.
+    #[clap(long)]
+    pub exec: Option<PathBuf>,
+    /// Whether `--exec` stops at the first failing command.
+    #[clap(long, value_enum, default_value_t)]
+    pub on_error: OnError,
+    /// Accept console commands from other programs on `host:port` or
+    /// `unix:/path/to/socket`, one per line.
+    #[clap(long)]
+    pub listen: Option<ListenAddress>,
+    /// Read console commands from stdin, one per line, and print their
+    /// output. With `--headless` they run before the frame is rendered, so
+    /// nothing is rendered or saved until stdin closes or reads `exit`.
+    #[clap(long)]
+    pub stdin: bool,
+    /// Most verbose log level to print.
+    #[clap(long, default_value = "info")]
+    pub log_level: log::LevelFilter,
+    #[clap(flatten)]
+    pub graphics: GraphicsArgs,
+}
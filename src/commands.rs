@@ -1,7 +1,16 @@
-use clap::{Args, CommandFactory, Parser, Subcommand};
-use log::{error, warn};
+use std::path::PathBuf;
 
-use crate::state::State;
+use cgmath::{InnerSpace, Quaternion, Vector3};
+use clap::{error::ErrorKind, Args, CommandFactory, Parser, Subcommand};
+
+use crate::{
+    cvars::CvarError,
+    engine_state::EngineError,
+    noise::{FractalMode, NoiseUniform, MAX_OCTAVES},
+    script::{run_script, OnError, ScriptError},
+    state::State,
+    ui::console::EntryKind,
+};
 
 #[derive(Parser, Debug)]
 pub struct Command {
@@ -15,9 +24,19 @@ pub enum SubCommands {
         name: String,
     },
     Noise(NoiseArgs),
+    /// Set a console variable, vectors take three numbers.
     Set {
         name: String,
-        value: String,
+        #[clap(required = true, allow_hyphen_values = true)]
+        value: Vec<String>,
+    },
+    /// Show a console variable and its help.
+    Get {
+        name: String,
+    },
+    /// List the console variables, optionally only those containing `filter`.
+    Cvars {
+        filter: Option<String>,
     },
     Spawn {
         name: String,
@@ -42,15 +61,67 @@ pub enum SubCommands {
         z: f32,
         w: f32,
     },
+    /// Change what gets logged and what the console shows of it.
+    #[clap(subcommand)]
+    Log(LogCommands),
+    /// Run the commands in a script file.
+    Exec {
+        path: PathBuf,
+        #[clap(long, value_enum, default_value_t)]
+        on_error: OnError,
+    },
     HelpMe,
 }
 
+#[derive(Subcommand, Debug)]
+pub enum LogCommands {
+    /// Most verbose level logged, for the console and the terminal.
+    Level { level: log::LevelFilter },
+    /// Only show records of modules starting with `module` in the console,
+    /// every module without one.
+    Filter { module: Option<String> },
+}
+
 #[derive(Args, Debug)]
 pub struct NoiseArgs {
     #[clap(short, long)]
     seed: f64,
     #[clap(short, long)]
     copy_to_texture: String,
+    #[clap(short, long)]
+    frequency: Option<f32>,
+    #[clap(short, long, value_enum)]
+    mode: Option<FractalMode>,
+    #[clap(short, long, value_parser = clap::value_parser!(u32).range(1..=MAX_OCTAVES as i64))]
+    octaves: Option<u32>,
+    #[clap(short, long)]
+    lacunarity: Option<f32>,
+    #[clap(short, long)]
+    persistence: Option<f32>,
+    #[clap(short, long)]
+    amplitude: Option<f32>,
+    /// Also write the noise to this PNG file.
+    #[clap(long)]
+    save: Option<PathBuf>,
+}
+
+impl NoiseArgs {
+    /// Overrides the fields given on the command line, the rest keep their
+    /// current values.
+    fn apply(&self, uniform: &mut NoiseUniform) {
+        uniform.set_seed(self.seed as f32);
+        if let Some(frequency) = self.frequency {
+            uniform.set_frequency(frequency);
+        }
+
+        let mut fractal = uniform.fractal();
+        fractal.mode = self.mode.unwrap_or(fractal.mode);
+        fractal.octaves = self.octaves.unwrap_or(fractal.octaves);
+        fractal.lacunarity = self.lacunarity.unwrap_or(fractal.lacunarity);
+        fractal.persistence = self.persistence.unwrap_or(fractal.persistence);
+        fractal.amplitude = self.amplitude.unwrap_or(fractal.amplitude);
+        uniform.set_fractal(fractal);
+    }
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -59,8 +130,16 @@ pub enum CommandError {
     NotACommand,
     #[error("MisMatchedQuotes {0:?}")]
     ShellWordSplitError(#[from] shellwords::MismatchedQuotes),
-    #[error("Command Line Parse Error {0:?}")]
+    #[error("{0}")]
     ClapParseError(#[from] clap::Error),
+    #[error("{0}")]
+    EngineError(#[from] EngineError),
+    #[error("{0}")]
+    CvarError(#[from] CvarError),
+    #[error("Invalid value '{value}' for '{name}'")]
+    InvalidValue { name: String, value: String },
+    #[error("{0}")]
+    ScriptError(#[from] Box<ScriptError>),
 }
 
 impl Command {
@@ -68,6 +147,16 @@ impl Command {
         Self::command().render_long_help().to_string()
     }
 
+    /// Subcommand names, including clap's `help`.
+    pub fn subcommand_names() -> Vec<String> {
+        let mut command = Self::command();
+        command.build();
+        command
+            .get_subcommands()
+            .map(|subcommand| subcommand.get_name().to_string())
+            .collect()
+    }
+
     pub fn parse(cmd: &str) -> Result<Self, CommandError> {
         if !cmd.starts_with("lotus") {
             return Err(CommandError::NotACommand);
@@ -77,26 +166,130 @@ impl Command {
         Ok(result)
     }
 
-    pub fn command_to_state(command: &str, state: &mut State) {
-        match Self::parse(command) {
-            Ok(cmd) => match cmd.commands {
-                SubCommands::Pipeline { name } => todo!(),
-                SubCommands::Noise(noise_args) => todo!(),
-                SubCommands::Set { name, value } => todo!(),
-                SubCommands::Spawn { name, mesh } => {}
-                SubCommands::Position { name, x, y, z } => todo!(),
-                SubCommands::Rotation { name, x, y, z, w } => todo!(),
-                SubCommands::HelpMe => {
-                    state.console_node.add_to_history(&Self::help_string());
+    /// Parses and executes `cmd`. Asking for help, like `lotus help noise` or
+    /// `lotus noise --help`, outputs clap's help for that subcommand.
+    pub fn run(cmd: &str, state: &mut State) -> Result<String, CommandError> {
+        match Self::parse(cmd) {
+            Ok(command) => command.execute(state),
+            Err(CommandError::ClapParseError(err))
+                if matches!(
+                    err.kind(),
+                    ErrorKind::DisplayHelp | ErrorKind::DisplayHelpOnMissingArgumentOrSubcommand
+                ) =>
+            {
+                Ok(err.render().to_string())
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    pub fn execute(self, state: &mut State) -> Result<String, CommandError> {
+        match self.commands {
+            SubCommands::Pipeline { name } => {
+                state.set_render_pipeline(&name)?;
+                Ok(format!("Switched to pipeline '{name}'"))
+            }
+            SubCommands::Noise(noise_args) => {
+                let mut uniform = state.noise_uniform();
+                noise_args.apply(&mut uniform);
+                state.generate_noise(
+                    uniform,
+                    &noise_args.copy_to_texture,
+                    noise_args.save.clone(),
+                )?;
+                let fractal = uniform.fractal();
+                let mut output = format!(
+                    "Generated {} noise with seed {} and {} octaves into '{}'",
+                    fractal.mode.name(),
+                    noise_args.seed,
+                    fractal.octaves,
+                    noise_args.copy_to_texture
+                );
+                if let Some(path) = &noise_args.save {
+                    output += &format!(", saving to {path:?}");
                 }
-                _ => todo!(),
-            },
-            Err(err) => {
-                match err {
-                    CommandError::NotACommand => {}
-                    _ => warn!("{:?}", err),
+                Ok(output)
+            }
+            SubCommands::Set { name, value } => {
+                let value = state.set_cvar(&name, &value.join(" "))?;
+                Ok(format!("{name} = {value}"))
+            }
+            SubCommands::Get { name } => Ok(state.cvars().get(&name)?.to_string()),
+            SubCommands::Cvars { filter } => {
+                let filter = filter.unwrap_or_default();
+                let lines: Vec<_> = state
+                    .cvars()
+                    .iter()
+                    .filter(|cvar| cvar.name().contains(&filter))
+                    .map(|cvar| cvar.to_string())
+                    .collect();
+                Ok(lines.join("\n"))
+            }
+            SubCommands::Spawn { name, mesh } => {
+                state.spawn(name.clone(), &mesh)?;
+                Ok(format!("Spawned '{name}' with mesh '{mesh}'"))
+            }
+            SubCommands::Scale { name, x, y, z } => {
+                state.update_entity(&name, |instance| instance.scale = Vector3::new(x, y, z))?;
+                Ok(format!("Scaled '{name}' to ({x}, {y}, {z})"))
+            }
+            SubCommands::Position { name, x, y, z } => {
+                state.update_entity(&name, |instance| instance.position = Vector3::new(x, y, z))?;
+                Ok(format!("Moved '{name}' to ({x}, {y}, {z})"))
+            }
+            SubCommands::Rotation { name, x, y, z, w } => {
+                let rotation = Quaternion::new(w, x, y, z);
+                if rotation.magnitude2() == 0.0 {
+                    return Err(CommandError::InvalidValue {
+                        name: "rotation".into(),
+                        value: format!("({x}, {y}, {z}, {w})"),
+                    });
+                }
+                state.update_entity(&name, |instance| instance.rotation = rotation.normalize())?;
+                Ok(format!("Rotated '{name}' to ({x}, {y}, {z}, {w})"))
+            }
+            SubCommands::Log(LogCommands::Level { level }) => {
+                log::set_max_level(level);
+                Ok(format!("Log level {level}"))
+            }
+            SubCommands::Log(LogCommands::Filter { module }) => {
+                let output = match &module {
+                    Some(module) => format!("Showing logs of '{module}'"),
+                    None => "Showing logs of every module".to_string(),
                 };
+                state.console_log().set_module_filter(module);
+                Ok(output)
+            }
+            SubCommands::Exec { path, on_error } => {
+                let report = run_script(&path, on_error, state).map_err(Box::new)?;
+                Ok(format!(
+                    "Ran {path:?}, {} commands, {} failed",
+                    report.executed, report.failed
+                ))
             }
-        };
+            SubCommands::HelpMe => Ok(Self::help_string()),
+        }
+    }
+
+    /// Runs `command` and writes its output or error to the console history.
+    pub fn command_to_state(command: &str, state: &mut State) -> Result<String, CommandError> {
+        let result = Self::run(command, state);
+        state.refresh_console_completions();
+        match result {
+            Ok(output) => {
+                state
+                    .console_node()
+                    .add_to_history(EntryKind::Output, &output);
+                Ok(output)
+            }
+            Err(err) => {
+                if !matches!(err, CommandError::NotACommand) {
+                    state
+                        .console_node()
+                        .add_to_history(EntryKind::Error, &err.to_string());
+                }
+                Err(err)
+            }
+        }
     }
 }
@@ -0,0 +1,73 @@
+use std::sync::{Arc, Mutex};
+
+use fast_log::appender::{Command, FastLogRecord, LogAppender};
+
+/// Records not yet picked up by the console, older ones are dropped first.
+const MAX_PENDING: usize = 512;
+
+#[derive(Debug, Clone)]
+pub struct LogLine {
+    pub level: log::Level,
+    pub module: String,
+    pub message: String,
+}
+
+#[derive(Default)]
+struct Shared {
+    pending: Vec<LogLine>,
+    /// Only records from modules starting with this reach the console.
+    module_filter: Option<String>,
+}
+
+/// Log records mirrored into the in-game console. Clones share the same
+/// records, one goes to the logger and one to the console.
+#[derive(Clone, Default)]
+pub struct ConsoleLog {
+    shared: Arc<Mutex<Shared>>,
+}
+
+impl ConsoleLog {
+    /// Appender to register with `fast_log::Config::custom`.
+    pub fn appender(&self) -> ConsoleLogAppender {
+        ConsoleLogAppender { log: self.clone() }
+    }
+
+    pub fn take_lines(&self) -> Vec<LogLine> {
+        std::mem::take(&mut self.shared.lock().unwrap().pending)
+    }
+
+    pub fn set_module_filter(&self, module: Option<String>) {
+        self.shared.lock().unwrap().module_filter = module;
+    }
+}
+
+pub struct ConsoleLogAppender {
+    log: ConsoleLog,
+}
+
+impl LogAppender for ConsoleLogAppender {
+    fn do_logs(&mut self, records: &[FastLogRecord]) {
+        let mut shared = self.log.shared.lock().unwrap();
+        for record in records {
+            if !matches!(record.command, Command::CommandRecord) {
+                continue;
+            }
+            if let Some(filter) = &shared.module_filter {
+                if !record.module_path.starts_with(filter.as_str()) {
+                    continue;
+                }
+            }
+
+            shared.pending.push(LogLine {
+                level: record.level,
+                module: record.module_path.clone(),
+                message: record.args.clone(),
+            });
+        }
+
+        let len = shared.pending.len();
+        if len > MAX_PENDING {
+            shared.pending.drain(..len - MAX_PENDING);
+        }
+    }
+}
@@ -0,0 +1,327 @@
+use std::{collections::HashMap, fmt::Display};
+
+use cgmath::Vector3;
+
+use crate::state::State;
+
+/// Called after `lotus set` changes a cvar, with the new value.
+pub type OnChange = fn(&mut State, &CvarValue);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CvarValue {
+    Bool(bool),
+    Int(i64),
+    Float(f32),
+    String(String),
+    Vec3(Vector3<f32>),
+}
+
+impl CvarValue {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            CvarValue::Bool(_) => "bool",
+            CvarValue::Int(_) => "int",
+            CvarValue::Float(_) => "float",
+            CvarValue::String(_) => "string",
+            CvarValue::Vec3(_) => "vec3",
+        }
+    }
+
+    /// Parses `value` as the same kind as `self`. Vectors are three numbers
+    /// separated by spaces or commas.
+    fn parse_same_kind(&self, value: &str) -> Option<Self> {
+        let value = value.trim();
+        Some(match self {
+            CvarValue::Bool(_) => CvarValue::Bool(match value {
+                "true" | "1" | "on" => true,
+                "false" | "0" | "off" => false,
+                _ => return None,
+            }),
+            CvarValue::Int(_) => CvarValue::Int(value.parse().ok()?),
+            CvarValue::Float(_) => CvarValue::Float(value.parse().ok()?),
+            CvarValue::String(_) => CvarValue::String(value.to_owned()),
+            CvarValue::Vec3(_) => {
+                let components = value
+                    .split(|c: char| c == ',' || c.is_whitespace())
+                    .filter(|component| !component.is_empty())
+                    .map(|component| component.parse().ok())
+                    .collect::<Option<Vec<f32>>>()?;
+                let [x, y, z] = components[..] else {
+                    return None;
+                };
+                CvarValue::Vec3(Vector3::new(x, y, z))
+            }
+        })
+    }
+
+    /// Numbers that have to be within a cvar's range.
+    fn numbers(&self) -> Vec<f64> {
+        match self {
+            CvarValue::Int(value) => vec![*value as f64],
+            CvarValue::Float(value) => vec![*value as f64],
+            CvarValue::Vec3(value) => vec![value.x as f64, value.y as f64, value.z as f64],
+            CvarValue::Bool(_) | CvarValue::String(_) => Vec::new(),
+        }
+    }
+}
+
+impl Display for CvarValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CvarValue::Bool(value) => write!(f, "{value}"),
+            CvarValue::Int(value) => write!(f, "{value}"),
+            CvarValue::Float(value) => write!(f, "{value}"),
+            CvarValue::String(value) => write!(f, "{value:?}"),
+            CvarValue::Vec3(value) => write!(f, "{} {} {}", value.x, value.y, value.z),
+        }
+    }
+}
+
+impl From<bool> for CvarValue {
+    fn from(value: bool) -> Self {
+        CvarValue::Bool(value)
+    }
+}
+
+impl From<i64> for CvarValue {
+    fn from(value: i64) -> Self {
+        CvarValue::Int(value)
+    }
+}
+
+impl From<f32> for CvarValue {
+    fn from(value: f32) -> Self {
+        CvarValue::Float(value)
+    }
+}
+
+impl From<String> for CvarValue {
+    fn from(value: String) -> Self {
+        CvarValue::String(value)
+    }
+}
+
+impl From<Vector3<f32>> for CvarValue {
+    fn from(value: Vector3<f32>) -> Self {
+        CvarValue::Vec3(value)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum CvarError {
+    #[error("Unknown cvar '{0}'")]
+    Unknown(String),
+    #[error("'{value}' is not a {kind} value for '{name}'")]
+    InvalidValue {
+        name: String,
+        value: String,
+        kind: &'static str,
+    },
+    #[error("{value} is outside {min}..={max} for '{name}'")]
+    OutOfRange {
+        name: String,
+        value: String,
+        min: f64,
+        max: f64,
+    },
+}
+
+/// Console variable, a named engine setting that can be changed at runtime.
+pub struct Cvar {
+    name: String,
+    help: String,
+    default: CvarValue,
+    value: CvarValue,
+    min: f64,
+    max: f64,
+    on_change: Vec<OnChange>,
+}
+
+impl Cvar {
+    pub fn new(name: &str, help: &str, default: impl Into<CvarValue>) -> Self {
+        let default = default.into();
+        Self {
+            name: name.into(),
+            help: help.into(),
+            value: default.clone(),
+            default,
+            min: f64::NEG_INFINITY,
+            max: f64::INFINITY,
+            on_change: Vec::new(),
+        }
+    }
+
+    /// Limits numbers, and every component of vectors, to `min..=max`.
+    pub fn range(mut self, min: f64, max: f64) -> Self {
+        self.min = min;
+        self.max = max;
+        self
+    }
+
+    pub fn on_change(mut self, on_change: OnChange) -> Self {
+        self.on_change.push(on_change);
+        self
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn value(&self) -> &CvarValue {
+        &self.value
+    }
+
+    fn parse(&self, value: &str) -> Result<CvarValue, CvarError> {
+        let parsed = self
+            .value
+            .parse_same_kind(value)
+            .ok_or_else(|| CvarError::InvalidValue {
+                name: self.name.clone(),
+                value: value.into(),
+                kind: self.value.kind(),
+            })?;
+        let in_range = parsed
+            .numbers()
+            .into_iter()
+            .all(|number| (self.min..=self.max).contains(&number));
+        if !in_range {
+            return Err(CvarError::OutOfRange {
+                name: self.name.clone(),
+                value: value.into(),
+                min: self.min,
+                max: self.max,
+            });
+        }
+
+        Ok(parsed)
+    }
+}
+
+impl Display for Cvar {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} = {}", self.name, self.value)?;
+        if self.value != self.default {
+            write!(f, " (default {})", self.default)?;
+        }
+        write!(f, " [{}] {}", self.value.kind(), self.help)
+    }
+}
+
+#[derive(Default)]
+pub struct CvarRegistry {
+    cvars: HashMap<String, Cvar>,
+}
+
+impl CvarRegistry {
+    pub fn register(&mut self, cvar: Cvar) {
+        self.cvars.insert(cvar.name.clone(), cvar);
+    }
+
+    pub fn get(&self, name: &str) -> Result<&Cvar, CvarError> {
+        self.cvars
+            .get(name)
+            .ok_or_else(|| CvarError::Unknown(name.into()))
+    }
+
+    /// Parses and stores `value`, returning the callbacks to notify. They
+    /// need the whole `State`, so `State::set_cvar` calls them.
+    pub fn set(&mut self, name: &str, value: &str) -> Result<Vec<OnChange>, CvarError> {
+        let cvar = self
+            .cvars
+            .get_mut(name)
+            .ok_or_else(|| CvarError::Unknown(name.into()))?;
+        cvar.value = cvar.parse(value)?;
+
+        Ok(cvar.on_change.clone())
+    }
+
+    /// Stores a value that was changed elsewhere, like in the settings ui,
+    /// without notifying anyone.
+    pub fn store(&mut self, name: &str, value: impl Into<CvarValue>) {
+        if let Some(cvar) = self.cvars.get_mut(name) {
+            cvar.value = value.into();
+        }
+    }
+
+    /// Cvars sorted by name.
+    pub fn iter(&self) -> impl Iterator<Item = &Cvar> {
+        let mut cvars: Vec<_> = self.cvars.values().collect();
+        cvars.sort_by(|a, b| a.name.cmp(&b.name));
+        cvars.into_iter()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> CvarRegistry {
+        let mut cvars = CvarRegistry::default();
+        cvars.register(Cvar::new("bool", "", false));
+        cvars.register(Cvar::new("int", "", 4_i64).range(1.0, 16.0));
+        cvars.register(Cvar::new("float", "", 1.0_f32).range(0.0, f64::INFINITY));
+        cvars.register(Cvar::new("string", "", String::from("a")));
+        cvars.register(Cvar::new("vec3", "", Vector3::new(0.0, 0.0, 0.0)).range(0.0, 1.0));
+        cvars
+    }
+
+    fn set(name: &str, value: &str) -> Result<CvarValue, CvarError> {
+        let mut cvars = registry();
+        cvars.set(name, value)?;
+        Ok(cvars.get(name).unwrap().value().clone())
+    }
+
+    #[test]
+    fn parses_each_kind() {
+        assert_eq!(set("bool", "on").unwrap(), CvarValue::Bool(true));
+        assert_eq!(set("bool", " 0 ").unwrap(), CvarValue::Bool(false));
+        assert_eq!(set("int", "12").unwrap(), CvarValue::Int(12));
+        assert_eq!(set("float", "2.5").unwrap(), CvarValue::Float(2.5));
+        assert_eq!(
+            set("string", "some path.png").unwrap(),
+            CvarValue::String("some path.png".into())
+        );
+        assert_eq!(
+            set("vec3", "0.1, 0.2 0.3").unwrap(),
+            CvarValue::Vec3(Vector3::new(0.1, 0.2, 0.3))
+        );
+    }
+
+    #[test]
+    fn rejects_values_of_another_kind() {
+        for (name, value) in [
+            ("bool", "yes"),
+            ("int", "1.5"),
+            ("float", "fast"),
+            ("vec3", "0.1 0.2"),
+            ("vec3", "0.1 0.2 0.3 0.4"),
+        ] {
+            assert!(
+                matches!(set(name, value), Err(CvarError::InvalidValue { .. })),
+                "{name} = {value}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_values_out_of_range() {
+        for (name, value) in [
+            ("int", "0"),
+            ("int", "17"),
+            ("float", "-0.5"),
+            ("float", "NaN"),
+            ("vec3", "0.5 1.5 0.5"),
+        ] {
+            assert!(
+                matches!(set(name, value), Err(CvarError::OutOfRange { .. })),
+                "{name} = {value}"
+            );
+        }
+        assert_eq!(set("int", "16").unwrap(), CvarValue::Int(16));
+    }
+
+    #[test]
+    fn unknown_cvar() {
+        assert!(matches!(set("missing", "1"), Err(CvarError::Unknown(_))));
+    }
+}
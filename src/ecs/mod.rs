@@ -1 +1,5 @@
 pub mod ecs;
+pub mod query;
+pub mod resources;
+pub mod storage;
+pub mod world;
@@ -0,0 +1,231 @@
+use std::{any::TypeId, marker::PhantomData};
+
+use super::{
+    storage::SparseSet,
+    world::{Entity, World},
+};
+
+/// Components a query borrows, `&T`, `&mut T` or a tuple of them.
+///
+/// # Safety
+/// `access` has to list every component `get` borrows, `World::query_mut`
+/// relies on it to rule out aliasing `&mut`.
+pub unsafe trait Query {
+    type Item<'w>;
+    /// Pointers to the storages the query reads.
+    type Fetch: Copy;
+
+    /// Adds the component types the query borrows, `true` when mutably.
+    fn access(access: &mut Vec<(TypeId, bool)>);
+
+    /// `None` when a component has never been added, so nothing matches.
+    fn fetch(world: &World) -> Option<Self::Fetch>;
+
+    /// Entities that may match, the smallest storage of the query.
+    ///
+    /// # Safety
+    /// `fetch` has to come from a world that outlives `'w`.
+    unsafe fn entities<'w>(fetch: Self::Fetch) -> &'w [Entity];
+
+    /// # Safety
+    /// As `entities`, and `entity` can't be fetched again while the item
+    /// is alive if the query borrows mutably.
+    unsafe fn get<'w>(fetch: Self::Fetch, entity: Entity) -> Option<Self::Item<'w>>;
+}
+
+/// Queries that only read, so `World::query` can run them on `&World`.
+///
+/// # Safety
+/// `Query::get` must not hand out `&mut`.
+pub unsafe trait ReadOnlyQuery: Query {}
+
+unsafe impl<T: 'static> Query for &T {
+    type Item<'w> = &'w T;
+    type Fetch = *mut SparseSet<T>;
+
+    fn access(access: &mut Vec<(TypeId, bool)>) {
+        access.push((TypeId::of::<T>(), false));
+    }
+
+    fn fetch(world: &World) -> Option<Self::Fetch> {
+        world.storage_ptr::<T>()
+    }
+
+    unsafe fn entities<'w>(fetch: Self::Fetch) -> &'w [Entity] {
+        unsafe { (*fetch).entities() }
+    }
+
+    unsafe fn get<'w>(fetch: Self::Fetch, entity: Entity) -> Option<Self::Item<'w>> {
+        unsafe { (*fetch).get(entity) }
+    }
+}
+
+unsafe impl<T: 'static> ReadOnlyQuery for &T {}
+
+unsafe impl<T: 'static> Query for &mut T {
+    type Item<'w> = &'w mut T;
+    type Fetch = *mut SparseSet<T>;
+
+    fn access(access: &mut Vec<(TypeId, bool)>) {
+        access.push((TypeId::of::<T>(), true));
+    }
+
+    fn fetch(world: &World) -> Option<Self::Fetch> {
+        world.storage_ptr::<T>()
+    }
+
+    unsafe fn entities<'w>(fetch: Self::Fetch) -> &'w [Entity] {
+        unsafe { (*fetch).entities() }
+    }
+
+    unsafe fn get<'w>(fetch: Self::Fetch, entity: Entity) -> Option<Self::Item<'w>> {
+        // Through the pointer, a `&mut` to the storage would invalidate the
+        // items handed out before.
+        unsafe { SparseSet::get_ptr(fetch, entity).map(|component| &mut *component) }
+    }
+}
+
+macro_rules! impl_query {
+    ($($name:ident),+) => {
+        #[allow(non_snake_case)]
+        unsafe impl<$($name: Query),+> Query for ($($name,)+) {
+            type Item<'w> = ($($name::Item<'w>,)+);
+            type Fetch = ($($name::Fetch,)+);
+
+            fn access(access: &mut Vec<(TypeId, bool)>) {
+                $($name::access(access);)+
+            }
+
+            fn fetch(world: &World) -> Option<Self::Fetch> {
+                Some(($($name::fetch(world)?,)+))
+            }
+
+            unsafe fn entities<'w>(fetch: Self::Fetch) -> &'w [Entity] {
+                let ($($name,)+) = fetch;
+                let mut smallest: Option<&'w [Entity]> = None;
+                $(
+                    let entities = unsafe { $name::entities($name) };
+                    if smallest.map_or(true, |smallest| entities.len() < smallest.len()) {
+                        smallest = Some(entities);
+                    }
+                )+
+                smallest.unwrap_or_default()
+            }
+
+            unsafe fn get<'w>(fetch: Self::Fetch, entity: Entity) -> Option<Self::Item<'w>> {
+                let ($($name,)+) = fetch;
+                Some(($(unsafe { $name::get($name, entity)? },)+))
+            }
+        }
+
+        unsafe impl<$($name: ReadOnlyQuery),+> ReadOnlyQuery for ($($name,)+) {}
+    };
+}
+
+impl_query!(A);
+impl_query!(A, B);
+impl_query!(A, B, C);
+impl_query!(A, B, C, D);
+impl_query!(A, B, C, D, E);
+impl_query!(A, B, C, D, E, F);
+impl_query!(A, B, C, D, E, F, G);
+impl_query!(A, B, C, D, E, F, G, H);
+
+/// Entities matching `Q` with their components, from `World::query`.
+pub struct QueryIter<'w, Q: Query> {
+    fetch: Option<Q::Fetch>,
+    entities: &'w [Entity],
+    next: usize,
+    _world: PhantomData<&'w mut World>,
+}
+
+impl<'w, Q: Query> QueryIter<'w, Q> {
+    /// Callers make sure the world is borrowed mutably if `Q` writes.
+    pub(super) fn new(world: &'w World) -> Self {
+        let fetch = Q::fetch(world);
+        // SAFETY: the fetched storages live as long as the world borrow.
+        let entities = fetch.map_or(&[][..], |fetch| unsafe { Q::entities(fetch) });
+        Self {
+            fetch,
+            entities,
+            next: 0,
+            _world: PhantomData,
+        }
+    }
+}
+
+impl<'w, Q: Query> Iterator for QueryIter<'w, Q> {
+    type Item = (Entity, Q::Item<'w>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let fetch = self.fetch?;
+        while let Some(&entity) = self.entities.get(self.next) {
+            self.next += 1;
+            // SAFETY: every entity is visited once and `query_mut` checked
+            // that `Q` doesn't borrow a component mutably twice.
+            if let Some(item) = unsafe { Q::get(fetch, entity) } {
+                return Some((entity, item));
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mutable_items_can_be_held_together() {
+        let mut world = World::default();
+        let entities: Vec<Entity> = (0..4u32).map(|value| world.spawn((value,))).collect();
+
+        // Run under Miri to check that the items don't alias.
+        let mut items: Vec<(Entity, &mut u32)> = world.query_mut::<&mut u32>().collect();
+        for (_, value) in &mut items {
+            **value += 10;
+        }
+        for (_, value) in &mut items {
+            **value *= 2;
+        }
+
+        for (value, entity) in entities.into_iter().enumerate() {
+            assert_eq!(
+                world.get_component::<u32>(entity),
+                Some(&((value as u32 + 10) * 2))
+            );
+        }
+    }
+
+    #[test]
+    fn tuples_borrow_each_storage() {
+        let mut world = World::default();
+        let both = world.spawn((1u32, 2.0f32));
+        world.spawn((3u32,));
+
+        let items: Vec<(Entity, (&mut u32, &f32))> =
+            world.query_mut::<(&mut u32, &f32)>().collect();
+        assert_eq!(items.len(), 1);
+        for (_, (value, scale)) in items {
+            *value = (*value as f32 * scale) as u32;
+        }
+        assert_eq!(world.get_component::<u32>(both), Some(&2));
+    }
+
+    #[test]
+    #[should_panic(expected = "borrows a component mutably more than once")]
+    fn rejects_mutable_and_shared_borrow() {
+        let mut world = World::default();
+        world.spawn((1u32,));
+        let _ = world.query_mut::<(&mut u32, &u32)>();
+    }
+
+    #[test]
+    #[should_panic(expected = "borrows a component mutably more than once")]
+    fn rejects_two_mutable_borrows() {
+        let mut world = World::default();
+        world.spawn((1u32,));
+        let _ = world.query_mut::<(&mut u32, &mut u32)>();
+    }
+}
@@ -0,0 +1,120 @@
+use std::{
+    any::{Any, TypeId},
+    cell::{Ref, RefCell, RefMut},
+    collections::HashMap,
+};
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum ResourceError {
+    #[error("No {0} resource")]
+    Missing(&'static str),
+    #[error("Resource {0} is already borrowed")]
+    Borrowed(&'static str),
+}
+
+/// Values systems share that belong to no entity, one per type. Each one is
+/// borrowed on its own, so a system can change one while reading others.
+#[derive(Default)]
+pub struct Resources {
+    values: HashMap<TypeId, Box<dyn Any>>,
+}
+
+impl Resources {
+    /// Stores `value`, returning the one of the same type it replaces.
+    pub fn insert<T: 'static>(&mut self, value: T) -> Option<T> {
+        let old = self
+            .values
+            .insert(TypeId::of::<T>(), Box::new(RefCell::new(value)))?;
+        old.downcast::<RefCell<T>>()
+            .ok()
+            .map(|old| old.into_inner())
+    }
+
+    pub fn contains<T: 'static>(&self) -> bool {
+        self.values.contains_key(&TypeId::of::<T>())
+    }
+
+    /// Fails if `T` is missing or borrowed mutably.
+    pub fn get<T: 'static>(&self) -> Result<Ref<'_, T>, ResourceError> {
+        self.cell::<T>()?
+            .try_borrow()
+            .map_err(|_| ResourceError::Borrowed(std::any::type_name::<T>()))
+    }
+
+    /// Fails if `T` is missing or borrowed.
+    pub fn get_mut<T: 'static>(&self) -> Result<RefMut<'_, T>, ResourceError> {
+        self.cell::<T>()?
+            .try_borrow_mut()
+            .map_err(|_| ResourceError::Borrowed(std::any::type_name::<T>()))
+    }
+
+    pub fn remove<T: 'static>(&mut self) -> Option<T> {
+        let value = self.values.remove(&TypeId::of::<T>())?;
+        value
+            .downcast::<RefCell<T>>()
+            .ok()
+            .map(|value| value.into_inner())
+    }
+
+    fn cell<T: 'static>(&self) -> Result<&RefCell<T>, ResourceError> {
+        self.values
+            .get(&TypeId::of::<T>())
+            .and_then(|value| value.downcast_ref())
+            .ok_or(ResourceError::Missing(std::any::type_name::<T>()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn replaces_and_removes_by_type() {
+        let mut resources = Resources::default();
+        assert_eq!(resources.insert(1u32), None);
+        assert_eq!(resources.insert(2u32), Some(1));
+        resources.insert("name");
+
+        *resources.get_mut::<u32>().unwrap() += 1;
+        assert_eq!(*resources.get::<u32>().unwrap(), 3);
+        assert_eq!(resources.remove::<u32>(), Some(3));
+        assert!(!resources.contains::<u32>());
+        assert_eq!(
+            resources.get::<u32>().err(),
+            Some(ResourceError::Missing("u32"))
+        );
+        assert_eq!(*resources.get::<&str>().unwrap(), "name");
+    }
+
+    #[test]
+    fn borrows_each_type_on_its_own() {
+        let mut resources = Resources::default();
+        resources.insert(1u32);
+        resources.insert(2.0f32);
+
+        let mut int = resources.get_mut::<u32>().unwrap();
+        let float = resources.get::<f32>().unwrap();
+        *int += *float as u32;
+        assert_eq!(*int, 3);
+    }
+
+    #[test]
+    fn rejects_aliasing_borrows() {
+        let mut resources = Resources::default();
+        resources.insert(1u32);
+
+        let int = resources.get::<u32>().unwrap();
+        assert!(resources.get::<u32>().is_ok());
+        assert_eq!(
+            resources.get_mut::<u32>().err(),
+            Some(ResourceError::Borrowed("u32"))
+        );
+        drop(int);
+
+        let _int = resources.get_mut::<u32>().unwrap();
+        assert_eq!(
+            resources.get::<u32>().err(),
+            Some(ResourceError::Borrowed("u32"))
+        );
+    }
+}
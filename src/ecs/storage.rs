@@ -0,0 +1,95 @@
+use std::any::Any;
+
+use super::world::Entity;
+
+/// Type-erased component storage so `World` can despawn entities without
+/// knowing their component types.
+pub trait Storage: Any {
+    fn remove_entity(&mut self, entity: Entity);
+}
+
+/// Components of one type, packed densely for iteration. `sparse` maps an
+/// entity index to its position in `entities` and `components`.
+pub struct SparseSet<T> {
+    sparse: Vec<Option<usize>>,
+    entities: Vec<Entity>,
+    components: Vec<T>,
+}
+
+impl<T> Default for SparseSet<T> {
+    fn default() -> Self {
+        Self {
+            sparse: Vec::new(),
+            entities: Vec::new(),
+            components: Vec::new(),
+        }
+    }
+}
+
+impl<T> SparseSet<T> {
+    fn dense_index(&self, entity: Entity) -> Option<usize> {
+        let index = (*self.sparse.get(entity.index())?)?;
+        (self.entities[index] == entity).then_some(index)
+    }
+
+    /// Stores `component` for `entity`, returning the one it replaces.
+    pub fn insert(&mut self, entity: Entity, component: T) -> Option<T> {
+        if let Some(index) = self.dense_index(entity) {
+            return Some(std::mem::replace(&mut self.components[index], component));
+        }
+        if self.sparse.len() <= entity.index() {
+            self.sparse.resize(entity.index() + 1, None);
+        }
+        self.sparse[entity.index()] = Some(self.entities.len());
+        self.entities.push(entity);
+        self.components.push(component);
+
+        None
+    }
+
+    pub fn remove(&mut self, entity: Entity) -> Option<T> {
+        let index = self.dense_index(entity)?;
+        self.sparse[entity.index()] = None;
+        self.entities.swap_remove(index);
+        let component = self.components.swap_remove(index);
+        // The last component moved into the hole.
+        if let Some(moved) = self.entities.get(index) {
+            self.sparse[moved.index()] = Some(index);
+        }
+
+        Some(component)
+    }
+
+    pub fn get(&self, entity: Entity) -> Option<&T> {
+        let index = self.dense_index(entity)?;
+        Some(&self.components[index])
+    }
+
+    pub fn get_mut(&mut self, entity: Entity) -> Option<&mut T> {
+        let index = self.dense_index(entity)?;
+        Some(&mut self.components[index])
+    }
+
+    /// Pointer to the component of `entity`. Unlike `get_mut` it doesn't
+    /// borrow the other components, so queries can hand out several.
+    ///
+    /// # Safety
+    /// `this` has to point to a live storage.
+    pub unsafe fn get_ptr(this: *mut Self, entity: Entity) -> Option<*mut T> {
+        unsafe {
+            let index = (*this).dense_index(entity)?;
+            Some((*this).components.as_mut_ptr().add(index))
+        }
+    }
+
+    /// Entities that have this component, in storage order.
+    pub fn entities(&self) -> &[Entity] {
+        &self.entities
+    }
+}
+
+impl<T: 'static> Storage for SparseSet<T> {
+    fn remove_entity(&mut self, entity: Entity) {
+        self.remove(entity);
+    }
+}
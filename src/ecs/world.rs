@@ -0,0 +1,429 @@
+use std::{
+    any::{Any, TypeId},
+    cell::UnsafeCell,
+    collections::HashMap,
+    fmt::Display,
+    marker::PhantomData,
+};
+
+use super::{
+    query::{Query, QueryIter, ReadOnlyQuery},
+    storage::{SparseSet, Storage},
+};
+
+/// Slot index plus the generation of the slot when the entity was spawned,
+/// so handles to a despawned entity don't match the one reusing its slot.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Entity {
+    index: u32,
+    generation: u32,
+}
+
+impl Entity {
+    pub fn index(self) -> usize {
+        self.index as usize
+    }
+}
+
+impl Display for Entity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}v{}", self.index, self.generation)
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum EcsError {
+    #[error("Entity {0} doesn't exist")]
+    NoSuchEntity(Entity),
+}
+
+/// Entity spawned with a single component by `World::insert`.
+pub struct ResId<T>(Entity, PhantomData<T>);
+
+impl<T: 'static> std::fmt::Debug for ResId<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "TypeId: {:?}, TypeName: {}, entity: {}",
+            TypeId::of::<T>(),
+            std::any::type_name::<T>(),
+            self.0
+        )
+    }
+}
+impl<T: 'static> Clone for ResId<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T: 'static> Copy for ResId<T> {}
+
+impl<T> ResId<T> {
+    pub fn entity(&self) -> Entity {
+        self.0
+    }
+}
+
+/// Components spawned together, a tuple of them.
+pub trait Bundle {
+    fn add_to(self, world: &mut World, entity: Entity);
+}
+
+macro_rules! impl_bundle {
+    ($($name:ident),*) => {
+        impl<$($name: 'static),*> Bundle for ($($name,)*) {
+            #[allow(non_snake_case, unused_variables)]
+            fn add_to(self, world: &mut World, entity: Entity) {
+                let ($($name,)*) = self;
+                $(world.storage_or_default::<$name>().insert(entity, $name);)*
+            }
+        }
+    };
+}
+
+impl_bundle!();
+impl_bundle!(A);
+impl_bundle!(A, B);
+impl_bundle!(A, B, C);
+impl_bundle!(A, B, C, D);
+impl_bundle!(A, B, C, D, E);
+impl_bundle!(A, B, C, D, E, F);
+impl_bundle!(A, B, C, D, E, F, G);
+impl_bundle!(A, B, C, D, E, F, G, H);
+
+#[derive(Default)]
+struct Slot {
+    /// Bumped when the slot's entity is despawned.
+    generation: u32,
+    alive: bool,
+}
+
+/// Entities and their components, one sparse set per component type.
+#[derive(Default)]
+pub struct World {
+    slots: Vec<Slot>,
+    /// Slots of despawned entities, reused before new ones are made.
+    free: Vec<u32>,
+    /// Queries hand out `&mut` to several storages at once, so they're
+    /// reached through raw pointers while the world is borrowed mutably.
+    storages: HashMap<TypeId, Box<UnsafeCell<dyn Storage>>>,
+}
+
+impl World {
+    pub fn spawn(&mut self, bundle: impl Bundle) -> Entity {
+        let index = self.free.pop().unwrap_or_else(|| {
+            self.slots.push(Slot::default());
+            self.slots.len() as u32 - 1
+        });
+        let slot = &mut self.slots[index as usize];
+        slot.alive = true;
+        let entity = Entity {
+            index,
+            generation: slot.generation,
+        };
+        bundle.add_to(self, entity);
+
+        entity
+    }
+
+    /// Removes `entity` with all its components, `false` if it was already gone.
+    pub fn despawn(&mut self, entity: Entity) -> bool {
+        if !self.contains(entity) {
+            return false;
+        }
+        for storage in self.storages.values_mut() {
+            storage.get_mut().remove_entity(entity);
+        }
+        let slot = &mut self.slots[entity.index()];
+        slot.alive = false;
+        // A slot whose generation ran out is never reused.
+        if let Some(generation) = slot.generation.checked_add(1) {
+            slot.generation = generation;
+            self.free.push(entity.index);
+        }
+
+        true
+    }
+
+    pub fn contains(&self, entity: Entity) -> bool {
+        self.slots
+            .get(entity.index())
+            .is_some_and(|slot| slot.alive && slot.generation == entity.generation)
+    }
+
+    /// Adds `component` to `entity`, returning the one of the same type it replaces.
+    pub fn add<T: 'static>(&mut self, entity: Entity, component: T) -> Result<Option<T>, EcsError> {
+        if !self.contains(entity) {
+            return Err(EcsError::NoSuchEntity(entity));
+        }
+
+        Ok(self.storage_or_default::<T>().insert(entity, component))
+    }
+
+    pub fn remove_component<T: 'static>(&mut self, entity: Entity) -> Option<T> {
+        self.storage_mut::<T>()?.remove(entity)
+    }
+
+    pub fn get_component<T: 'static>(&self, entity: Entity) -> Option<&T> {
+        self.storage::<T>()?.get(entity)
+    }
+
+    pub fn get_component_mut<T: 'static>(&mut self, entity: Entity) -> Option<&mut T> {
+        self.storage_mut::<T>()?.get_mut(entity)
+    }
+
+    /// Entities that have every component in `Q`, like
+    /// `world.query::<(&Transform, &MeshHandle)>()`.
+    pub fn query<Q: ReadOnlyQuery>(&self) -> QueryIter<'_, Q> {
+        QueryIter::new(self)
+    }
+
+    /// Like `query`, but `Q` can also borrow components mutably.
+    pub fn query_mut<Q: Query>(&mut self) -> QueryIter<'_, Q> {
+        let mut access = Vec::new();
+        Q::access(&mut access);
+        for (index, (type_id, mutable)) in access.iter().enumerate() {
+            let aliased = access[index + 1..]
+                .iter()
+                .any(|(other, other_mutable)| other == type_id && (*mutable || *other_mutable));
+            assert!(
+                !aliased,
+                "{} borrows a component mutably more than once",
+                std::any::type_name::<Q>()
+            );
+        }
+
+        QueryIter::new(self)
+    }
+
+    /// Spawns an entity with only `component`.
+    pub fn insert<T: 'static>(&mut self, component: T) -> ResId<T> {
+        ResId(self.spawn((component,)), PhantomData)
+    }
+
+    pub fn get<T: 'static>(&self, rid: &ResId<T>) -> Option<&T> {
+        self.get_component(rid.0)
+    }
+
+    /// Despawns the entity of `rid`, returning its component.
+    pub fn remove<T: 'static>(&mut self, rid: ResId<T>) -> Option<T> {
+        let component = self.remove_component(rid.0)?;
+        self.despawn(rid.0);
+
+        Some(component)
+    }
+
+    pub(super) fn storage<T: 'static>(&self) -> Option<&SparseSet<T>> {
+        let cell = self.storages.get(&TypeId::of::<T>())?;
+        // SAFETY: storages are only changed through `&mut self`, which
+        // can't coexist with this borrow.
+        let storage: &dyn Any = unsafe { &*cell.get() };
+        storage.downcast_ref()
+    }
+
+    /// Pointer for queries, only written through while they hold `&mut self`.
+    pub(super) fn storage_ptr<T: 'static>(&self) -> Option<*mut SparseSet<T>> {
+        let cell = self.storages.get(&TypeId::of::<T>())?;
+        // Storages are keyed by their component type.
+        Some(cell.get() as *mut SparseSet<T>)
+    }
+
+    fn storage_mut<T: 'static>(&mut self) -> Option<&mut SparseSet<T>> {
+        let storage: &mut dyn Any = self.storages.get_mut(&TypeId::of::<T>())?.get_mut();
+        storage.downcast_mut()
+    }
+
+    fn storage_or_default<T: 'static>(&mut self) -> &mut SparseSet<T> {
+        let storage: &mut dyn Any = self
+            .storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(UnsafeCell::new(SparseSet::<T>::default())))
+            .get_mut();
+        storage.downcast_mut().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::{rngs::StdRng, Rng, SeedableRng};
+
+    use super::*;
+
+    #[test]
+    fn despawn_bumps_the_generation() {
+        let mut world = World::default();
+        let old = world.spawn((1u32,));
+        assert!(world.despawn(old));
+        assert!(!world.despawn(old));
+
+        let new = world.spawn((2u32,));
+        assert_eq!(new.index(), old.index());
+        assert_eq!(new.generation, old.generation + 1);
+        assert_ne!(new, old);
+    }
+
+    #[test]
+    fn stale_entities_dont_resolve() {
+        let mut world = World::default();
+        let old = world.spawn((1u32,));
+        world.despawn(old);
+        let new = world.spawn((2u32,));
+
+        assert!(!world.contains(old));
+        assert_eq!(world.get_component::<u32>(old), None);
+        assert_eq!(world.get_component_mut::<u32>(old), None);
+        assert_eq!(world.remove_component::<u32>(old), None);
+        assert!(matches!(
+            world.add(old, 3u32),
+            Err(EcsError::NoSuchEntity(_))
+        ));
+        assert!(!world.despawn(old));
+        assert_eq!(world.get_component::<u32>(new), Some(&2));
+    }
+
+    #[test]
+    fn stale_res_ids_dont_resolve() {
+        let mut world = World::default();
+        let old = world.insert(1u32);
+        assert_eq!(world.remove(old), Some(1));
+        assert_eq!(world.get(&old), None);
+
+        let new = world.insert(2u32);
+        assert_eq!(new.entity().index(), old.entity().index());
+        assert_eq!(world.get(&old), None);
+        assert_eq!(world.remove(old), None);
+        assert_eq!(world.get(&new), Some(&2));
+    }
+
+    /// Components an entity should have.
+    #[derive(Default)]
+    struct Expected {
+        small: Option<u32>,
+        large: Option<u64>,
+    }
+
+    fn check(world: &World, model: &HashMap<Entity, Expected>, entity: Entity) {
+        let expected = model.get(&entity);
+        assert_eq!(world.contains(entity), expected.is_some());
+        assert_eq!(
+            world.get_component::<u32>(entity),
+            expected.and_then(|e| e.small.as_ref())
+        );
+        assert_eq!(
+            world.get_component::<u64>(entity),
+            expected.and_then(|e| e.large.as_ref())
+        );
+    }
+
+    fn check_all(world: &World, model: &HashMap<Entity, Expected>) {
+        let mut small = 0;
+        for (entity, value) in world.query::<&u32>() {
+            assert_eq!(model[&entity].small, Some(*value));
+            small += 1;
+        }
+        assert_eq!(small, model.values().filter(|e| e.small.is_some()).count());
+        let mut both = 0;
+        for (entity, (small, large)) in world.query::<(&u32, &u64)>() {
+            assert_eq!(model[&entity].small, Some(*small));
+            assert_eq!(model[&entity].large, Some(*large));
+            both += 1;
+        }
+        assert_eq!(
+            both,
+            model
+                .values()
+                .filter(|e| e.small.is_some() && e.large.is_some())
+                .count()
+        );
+    }
+
+    /// Random operations checked against a `HashMap` of what each entity
+    /// should have, including handles that were despawned.
+    #[test]
+    fn matches_hash_map_model() {
+        let mut rng = StdRng::seed_from_u64(0x10705);
+        let mut world = World::default();
+        let mut model: HashMap<Entity, Expected> = HashMap::new();
+        let mut alive: Vec<Entity> = Vec::new();
+        let mut dead: Vec<Entity> = Vec::new();
+
+        for op in 0..2_000_000 {
+            // Despawning more often past a few hundred entities keeps the world small.
+            let despawn_chance = if alive.len() > 500 { 0.3 } else { 0.1 };
+            let entity = match rng.gen_range(0..8) {
+                0 | 1 => {
+                    let small = rng.gen_bool(0.5).then(|| rng.gen::<u32>());
+                    let large = rng.gen_bool(0.5).then(|| rng.gen::<u64>());
+                    let entity = match (small, large) {
+                        (Some(small), Some(large)) => world.spawn((small, large)),
+                        (Some(small), None) => world.spawn((small,)),
+                        (None, Some(large)) => world.spawn((large,)),
+                        (None, None) => world.spawn(()),
+                    };
+                    assert!(!dead.contains(&entity), "{entity} was reused");
+                    assert!(model.insert(entity, Expected { small, large }).is_none());
+                    alive.push(entity);
+                    entity
+                }
+                2 if !alive.is_empty() => {
+                    let entity = alive[rng.gen_range(0..alive.len())];
+                    let value = rng.gen::<u32>();
+                    let old = world.add(entity, value).unwrap();
+                    assert_eq!(old, model.get_mut(&entity).unwrap().small.replace(value));
+                    entity
+                }
+                3 if !alive.is_empty() => {
+                    let entity = alive[rng.gen_range(0..alive.len())];
+                    let value = rng.gen::<u64>();
+                    let old = world.add(entity, value).unwrap();
+                    assert_eq!(old, model.get_mut(&entity).unwrap().large.replace(value));
+                    entity
+                }
+                4 if !alive.is_empty() => {
+                    let entity = alive[rng.gen_range(0..alive.len())];
+                    let removed = world.remove_component::<u32>(entity);
+                    assert_eq!(removed, model.get_mut(&entity).unwrap().small.take());
+                    entity
+                }
+                5 if !alive.is_empty() => {
+                    let entity = alive[rng.gen_range(0..alive.len())];
+                    if let Some(value) = world.get_component_mut::<u64>(entity) {
+                        *value = value.wrapping_add(1);
+                    }
+                    if let Some(value) = &mut model.get_mut(&entity).unwrap().large {
+                        *value = value.wrapping_add(1);
+                    }
+                    entity
+                }
+                6 if !dead.is_empty() => {
+                    let entity = dead[rng.gen_range(0..dead.len())];
+                    assert!(world.add(entity, 0u32).is_err());
+                    assert_eq!(world.remove_component::<u64>(entity), None);
+                    assert!(!world.despawn(entity));
+                    entity
+                }
+                _ if !alive.is_empty() && rng.gen_bool(despawn_chance) => {
+                    let entity = alive.swap_remove(rng.gen_range(0..alive.len()));
+                    assert!(world.despawn(entity));
+                    model.remove(&entity);
+                    // A bounded sample of stale handles to try again later.
+                    if dead.len() < 64 {
+                        dead.push(entity);
+                    } else {
+                        let replaced = rng.gen_range(0..dead.len());
+                        dead[replaced] = entity;
+                    }
+                    entity
+                }
+                _ => continue,
+            };
+
+            check(&world, &model, entity);
+            if op % 100_000 == 0 {
+                check_all(&world, &model);
+            }
+        }
+        check_all(&world, &model);
+    }
+}
@@ -48,7 +48,8 @@ impl TextureWithView {
             dimension: TextureDimension::D2,
             usage: TextureUsages::TEXTURE_BINDING
                 | TextureUsages::RENDER_ATTACHMENT
-                | TextureUsages::COPY_DST,
+                | TextureUsages::COPY_DST
+                | TextureUsages::COPY_SRC,
             view_formats: &[],
         };
 
@@ -60,15 +61,49 @@ impl TextureWithView {
     }
 }
 
+/// Line and point rendering are optional, adapters without them draw filled.
+pub fn supported_polygon_mode(device: &Device, polygon_mode: PolygonMode) -> PolygonMode {
+    let required_feature = match polygon_mode {
+        PolygonMode::Fill => return polygon_mode,
+        PolygonMode::Line => wgpu::Features::POLYGON_MODE_LINE,
+        PolygonMode::Point => wgpu::Features::POLYGON_MODE_POINT,
+    };
+
+    if device.features().contains(required_feature) {
+        polygon_mode
+    } else {
+        PolygonMode::Fill
+    }
+}
+
 #[derive(Debug)]
 pub enum EngineError {
     NameAlreadyExists,
+    NameNotFound(String),
+    SizeMismatch {
+        expected: (u32, u32),
+        found: (u32, u32),
+    },
+    IncompatibleFormat(TextureFormat),
 }
 
 impl std::error::Error for EngineError {}
 impl std::fmt::Display for EngineError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "Associated Unique Name is already in use for the type")
+        match self {
+            EngineError::NameAlreadyExists => {
+                write!(f, "Associated Unique Name is already in use for the type")
+            }
+            EngineError::NameNotFound(name) => write!(f, "No resource named '{name}'"),
+            EngineError::SizeMismatch { expected, found } => write!(
+                f,
+                "Size mismatch, expected {}x{} found {}x{}",
+                expected.0, expected.1, found.0, found.1
+            ),
+            EngineError::IncompatibleFormat(format) => {
+                write!(f, "Incompatible texture format {format:?}")
+            }
+        }
     }
 }
 
@@ -81,6 +116,22 @@ impl EngineState {
         self.samplers.get(sampler_name).cloned()
     }
 
+    pub fn get_texture(&self, texture_name: &str) -> Option<Res<TextureWithView>> {
+        self.textures.get(texture_name).cloned()
+    }
+
+    pub fn get_render_pipeline(&self, pipeline_name: &str) -> Option<Res<RenderPipeline>> {
+        self.render_pipelines.get(pipeline_name).cloned()
+    }
+
+    pub fn texture_names(&self) -> impl Iterator<Item = &str> {
+        self.textures.keys().map(String::as_str)
+    }
+
+    pub fn render_pipeline_names(&self) -> impl Iterator<Item = &str> {
+        self.render_pipelines.keys().map(String::as_str)
+    }
+
     pub fn create_texture(
         &mut self,
         texture_name: String,
@@ -108,7 +159,8 @@ impl EngineState {
             dimension: TextureDimension::D2,
             usage: TextureUsages::TEXTURE_BINDING
                 | TextureUsages::RENDER_ATTACHMENT
-                | TextureUsages::COPY_DST,
+                | TextureUsages::COPY_DST
+                | TextureUsages::COPY_SRC,
             view_formats: &[],
         };
 
@@ -205,7 +257,7 @@ impl EngineState {
                 strip_index_format: None,
                 front_face: wgpu::FrontFace::Ccw,
                 cull_mode: Some(wgpu::Face::Back),
-                polygon_mode,
+                polygon_mode: supported_polygon_mode(device, polygon_mode),
                 unclipped_depth: false,
                 conservative: false,
             },
@@ -1,7 +1,14 @@
-use std::time::Instant;
+use std::{path::{Path, PathBuf}, sync::Arc, time::{Duration, Instant}};
+use anyhow::Context;
+use app_config::{AppConfig, ConfigFile};
+use clap::Parser;
+use cli::Cli;
+use console_log::ConsoleLog;
+use script::OnError;
 use commands::Command;
-use log::{error, info};
+use log::{error, info, warn};
 use winit::{
+    dpi::PhysicalSize,
     event::{DeviceEvent, Event, WindowEvent},
     event_loop::{ControlFlow, EventLoopBuilder},
     window::WindowBuilder,
@@ -19,30 +26,55 @@ mod engine_state;
 mod commands;
 mod scene;
 mod client;
-use state::State;
+mod render_target;
+mod cli;
+mod script;
+mod cvars;
+mod console_log;
+mod remote_console;
+mod repl;
+mod schedule;
+mod systems;
+use state::{Startup, State};
+use voxel::terrain::TerrainConfig;
 
 
 #[derive(Debug)]
 enum CustomEvents {
     UserCommand(String),
+    /// A command from the remote console, its output goes to `reply`.
+    RemoteCommand {
+        command: String,
+        reply: std::sync::mpsc::Sender<remote_console::CommandReply>,
+    },
 }
 
-fn run() {
+fn run(startup: Startup, cli: &Cli) -> anyhow::Result<()> {
     
 
     let event_loop = EventLoopBuilder::<CustomEvents>::with_user_event().build().unwrap();
     // let event_loop = EventLoop::<CustomEvents>::new().unwrap();
     let window = WindowBuilder::new()
         .with_title("Lotus")
+        .with_inner_size(PhysicalSize::new(startup.config_file.config.window.width, startup.config_file.config.window.height))
         .build(&event_loop)
         .unwrap();
+    let window = Arc::new(window);
     let proxy = event_loop.create_proxy();
-    let mut state = tokio::runtime::Runtime::new()
-        .unwrap()
-        .block_on(State::new(&window, proxy.clone()))
-        .unwrap();
+    let autoexec = startup.config_file.autoexec_path();
+    let mut state = tokio::runtime::Runtime::new()?
+        .block_on(State::new(window.clone(), proxy.clone(), startup))?;
 
     info!("State initialized");
+    run_startup_scripts(&mut state, autoexec.as_deref(), cli)?;
+    // Kept until the event loop returns, which removes a unix socket.
+    let _listener = match &cli.listen {
+        Some(address) => Some(remote_console::listen(address, proxy.clone())?),
+        None => None,
+    };
+    if cli.stdin {
+        repl::spawn(proxy.clone());
+    }
 
     let mut last_render_time = Instant::now();
     event_loop
@@ -50,15 +82,15 @@ fn run() {
             // info!("EVent: {:?}", event);
             window_target.set_control_flow(ControlFlow::Poll);
             match event {
-                Event::NewEvents(_) => state.window().request_redraw(),
+                Event::NewEvents(_) => window.request_redraw(),
                 Event::DeviceEvent { device_id: _, event } => {
                   match event {
-                        DeviceEvent::MouseMotion { delta } => state.camera_controller.process_mouse(delta.0, delta.1),
+                        DeviceEvent::MouseMotion { delta } => state.camera_controller().process_mouse(delta.0, delta.1),
                         _ => {},
                     };
                 },
                 Event::WindowEvent { window_id, event } => {
-                    if window_id == state.window().id() {
+                    if window_id == window.id() {
                         if !state.input(&event) {
                             match event {
                                 WindowEvent::CloseRequested => {
@@ -101,7 +133,18 @@ fn run() {
                                 window_target.exit();
                             } else {
                                 info!("User issued a command '{}'", cmd);
-                                Command::command_to_state(&cmd, &mut state);
+                                let _ = Command::command_to_state(&cmd, &mut state);
+                            }
+                        },
+                        CustomEvents::RemoteCommand { command, reply } => {
+                            if command.trim() == "exit" {
+                                let _ = reply.send(Ok(String::new()));
+                                window_target.exit();
+                            } else {
+                                info!("Remote command '{}'", command);
+                                let result = Command::command_to_state(&command, &mut state);
+                                // The client may have disconnected already.
+                                let _ = reply.send(result.map_err(|err| err.to_string()));
                             }
                         },
                     }
@@ -111,23 +154,124 @@ fn run() {
             };
         })
         .unwrap();
+
+    Ok(())
 }
 
-fn main() {
-    let _ = fast_log::init(fast_log::Config::new().console().level(log::LevelFilter::Info)).unwrap();
-    info!("Initiating...");
+/// Config at `path` or in the platform config directory. A file that fails
+/// to load is left alone and the defaults are used without saving.
+fn load_config(path: Option<PathBuf>) -> ConfigFile {
+    let Some(path) = path.or_else(AppConfig::default_path) else {
+        warn!("No config directory, settings won't be saved");
+        return ConfigFile::default();
+    };
+    info!("Config path {:?}", path);
+
+    match AppConfig::load(&path) {
+        Ok(config) => ConfigFile { config, path: Some(path), changed: false },
+        Err(err) => {
+            error!("{err:#}, using the default config");
+            ConfigFile::default()
+        }
+    }
+}
+
+/// Runs the autoexec script if there is one, then `--exec`. Only `--exec`
+/// can stop the engine from starting.
+fn run_startup_scripts(state: &mut State, autoexec: Option<&Path>, cli: &Cli) -> anyhow::Result<()> {
+    if let Some(autoexec) = autoexec.filter(|path| path.exists()) {
+        if let Err(err) = script::run_script(autoexec, OnError::Continue, state) {
+            error!("{err}");
+        }
+    }
+    if let Some(exec) = &cli.exec {
+        script::run_script(exec, cli.on_error, state)?;
+    }
+
+    Ok(())
+}
 
+const HEADLESS_SIZE: (u32, u32) = (1280, 720);
 
-    // let stdin = std::io::stdin();
-    // let mut lines = stdin.lines();
-    // while let Some(Ok(line)) = lines.next() {
-    //     match commands::Command::try_parse_from(line.split(' ').skip_while(|x| x.is_empty())) {
-    //         Ok(command) => info!("{:?}", command),
-    //         Err(err) => error!("{err}\n{}", commands::Command::help_string()),
-    //     };
-        
-    // }
+/// Renders a single frame of the scene without a window and saves it to `output`.
+fn run_headless(startup: Startup, cli: &Cli) -> anyhow::Result<()> {
+    if cli.listen.is_some() {
+        warn!("--listen needs a window, ignoring it with --headless");
+    }
+    let autoexec = startup.config_file.autoexec_path();
+    let mut state = tokio::runtime::Runtime::new()?
+        .block_on(State::new_headless(HEADLESS_SIZE, startup))?;
+    info!("Headless state initialized");
+    run_startup_scripts(&mut state, autoexec.as_deref(), cli)?;
+    if cli.stdin {
+        repl::run(&mut state);
+    }
+
+    // Mesh every chunk first, a window would settle on the same frame after a few updates.
+    let dt = Duration::from_secs_f32(1.0 / 60.0);
+    state.update(dt);
+    while state.has_pending_chunk_meshes() {
+        state.update(dt);
+    }
+
+    state.render()?;
+    state.save_frame(&cli.output)?;
+    info!("Saved frame to {:?}", cli.output);
+
+    Ok(())
+}
+
+/// Terrain of the `--world` file, or a new random one.
+fn load_terrain(cli: &Cli) -> anyhow::Result<TerrainConfig> {
+    let mut terrain = match &cli.world {
+        Some(path) => TerrainConfig::load_or_create(path).context("Failed to load world")?,
+        None => TerrainConfig {
+            seed: rand::random::<f32>() * 10000.0,
+            ..Default::default()
+        },
+    };
+    if let Some(seed) = cli.seed {
+        terrain.seed = seed;
+    }
+
+    Ok(terrain)
+}
+
+fn run_cli(cli: Cli, console_log: ConsoleLog) -> anyhow::Result<()> {
+    let config_file = load_config(cli.config.clone());
+    // Command line overrides only last for this run, they are never saved.
+    let mut graphics = config_file.config.graphics.clone();
+    cli.graphics.apply(&mut graphics);
+    let startup = Startup {
+        graphics,
+        config_file,
+        terrain: load_terrain(&cli)?,
+        console_log,
+    };
+
+    if cli.headless {
+        run_headless(startup, &cli)
+    } else {
+        run(startup, &cli)
+    }
+}
+
+fn main() {
+    let cli = Cli::parse();
+    let console_log = ConsoleLog::default();
+    let _ = fast_log::init(
+        fast_log::Config::new()
+            .console()
+            .custom(console_log.appender())
+            .level(cli.log_level),
+    )
+    .unwrap();
+    info!("Initiating...");
 
-    run();
+    if let Err(err) = run_cli(cli, console_log) {
+        error!("{err:#}");
+        log::logger().flush();
+        std::process::exit(1);
+    }
 
 }
@@ -1,14 +1,96 @@
-use log::info;
+use std::{
+    path::{Path, PathBuf},
+    sync::mpsc::{self, Receiver, Sender, TryRecvError},
+    thread::JoinHandle,
+};
+
+use log::{error, info};
 use wgpu::{
     util::{BufferInitDescriptor, DeviceExt},
-    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayoutDescriptor,
-    BindGroupLayoutEntry, BindingType, Buffer, BufferDescriptor, BufferUsages, CommandEncoder,
-    ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device, Extent3d,
-    ImageCopyBuffer, PipelineLayoutDescriptor, Queue, ShaderModuleDescriptor, ShaderStages,
-    Texture,
+    BindGroup, BindGroupDescriptor, BindGroupEntry, BindGroupLayout, BindGroupLayoutDescriptor,
+    BindGroupLayoutEntry, BindingType, Buffer, BufferAsyncError, BufferDescriptor, BufferUsages,
+    CommandEncoder, ComputePassDescriptor, ComputePipeline, ComputePipelineDescriptor, Device,
+    Extent3d, ImageCopyBuffer, PipelineLayoutDescriptor, Queue, ShaderModuleDescriptor,
+    ShaderStages, Texture,
 };
 
-use crate::{state::save_tmp_image, ui::renderer::UiNode};
+use crate::ui::renderer::UiNode;
+
+/// Bytes per RGBA8 pixel.
+const PIXEL_SIZE: u32 = 4;
+/// Invocations per workgroup along x, matches `@workgroup_size` in `noise.wgsl`.
+const WORKGROUP_SIZE: u32 = 64;
+
+/// Octaves past this add detail finer than a pixel at any sensible frequency.
+pub const MAX_OCTAVES: u32 = 16;
+
+/// How the octaves of fractal noise are combined, matches `mode` in `noise.wgsl`.
+#[derive(
+    Debug,
+    Default,
+    Clone,
+    Copy,
+    PartialEq,
+    Eq,
+    serde::Serialize,
+    serde::Deserialize,
+    clap::ValueEnum,
+)]
+#[repr(u32)]
+pub enum FractalMode {
+    /// Plain sum of the octaves.
+    #[default]
+    Fbm = 0,
+    /// Inverted absolute value, sharp creases at the zero crossings.
+    Ridged = 1,
+    /// Absolute value, rounded bumps.
+    Billow = 2,
+}
+
+impl FractalMode {
+    pub const ALL: [FractalMode; 3] = [FractalMode::Fbm, FractalMode::Ridged, FractalMode::Billow];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            FractalMode::Fbm => "fbm",
+            FractalMode::Ridged => "ridged",
+            FractalMode::Billow => "billow",
+        }
+    }
+
+    fn from_u32(mode: u32) -> Self {
+        match mode {
+            1 => FractalMode::Ridged,
+            2 => FractalMode::Billow,
+            _ => FractalMode::Fbm,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct FractalSettings {
+    pub mode: FractalMode,
+    pub octaves: u32,
+    /// Frequency multiplier between octaves.
+    pub lacunarity: f32,
+    /// Amplitude multiplier between octaves.
+    pub persistence: f32,
+    /// Amplitude of the first octave.
+    pub amplitude: f32,
+}
+
+impl Default for FractalSettings {
+    /// A single octave of plain noise.
+    fn default() -> Self {
+        Self {
+            mode: FractalMode::Fbm,
+            octaves: 1,
+            lacunarity: 2.0,
+            persistence: 0.5,
+            amplitude: 1.0,
+        }
+    }
+}
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy, bytemuck::Pod, bytemuck::Zeroable)]
@@ -17,17 +99,32 @@ pub struct NoiseUniform {
     frequency: f32,
     offset: [f32; 2],
     size: [u32; 2],
+    octaves: u32,
+    mode: u32,
+    lacunarity: f32,
+    persistence: f32,
+    amplitude: f32,
+    /// Pixels per row of the output buffer, see `NoiseUniform::row_length`.
+    row_length: u32,
 }
 
 impl NoiseUniform {
     pub fn new(seed: f32, frequency: f32, offset: (f32, f32), size: (u32, u32)) -> Self {
         assert!(size != (0, 0));
-        Self {
+        let mut uniform = Self {
             seed,
             frequency,
             offset: [offset.0, offset.1],
             size: [size.0, size.1],
-        }
+            octaves: 0,
+            mode: 0,
+            lacunarity: 0.0,
+            persistence: 0.0,
+            amplitude: 0.0,
+            row_length: Self::row_length(size.0),
+        };
+        uniform.set_fractal(FractalSettings::default());
+        uniform
     }
 }
 
@@ -36,64 +133,379 @@ impl NoiseUniform {
         (self.size[0], self.size[1])
     }
 
+    pub fn set_seed(&mut self, seed: f32) {
+        self.seed = seed;
+    }
+
+    pub fn frequency(&self) -> f32 {
+        self.frequency
+    }
+
+    pub fn set_frequency(&mut self, frequency: f32) {
+        self.frequency = frequency;
+    }
+
+    pub fn fractal(&self) -> FractalSettings {
+        FractalSettings {
+            mode: FractalMode::from_u32(self.mode),
+            octaves: self.octaves,
+            lacunarity: self.lacunarity,
+            persistence: self.persistence,
+            amplitude: self.amplitude,
+        }
+    }
+
+    pub fn set_fractal(&mut self, fractal: FractalSettings) {
+        self.mode = fractal.mode as u32;
+        self.octaves = fractal.octaves;
+        self.lacunarity = fractal.lacunarity;
+        self.persistence = fractal.persistence;
+        self.amplitude = fractal.amplitude;
+    }
+
+    /// Rows of the output buffer are padded to the alignment buffer to texture
+    /// copies require.
+    fn row_length(width: u32) -> u32 {
+        width.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT / PIXEL_SIZE)
+    }
+
+    pub fn padded_bytes_per_row(&self) -> u32 {
+        self.row_length * PIXEL_SIZE
+    }
+
+    /// Size of the tightly packed image.
     pub fn image_size_in_bytes(&self) -> u64 {
         let (width, height) = self.size();
 
-        width as u64 * height as u64 * 4 * std::mem::size_of::<u8>() as u64
+        width as u64 * height as u64 * PIXEL_SIZE as u64
+    }
+
+    /// Size of the output buffer, including the row padding.
+    pub fn buffer_size_in_bytes(&self) -> u64 {
+        self.padded_bytes_per_row() as u64 * self.size[1] as u64
+    }
+
+    /// Strips the row padding from the output buffer contents.
+    pub fn unpad_rows(&self, data: &[u8]) -> Vec<u8> {
+        let row_size = (self.size[0] * PIXEL_SIZE) as usize;
+        let mut image = Vec::with_capacity(self.image_size_in_bytes() as usize);
+        for row in data.chunks(self.padded_bytes_per_row() as usize) {
+            image.extend_from_slice(&row[..row_size]);
+        }
+
+        image
     }
 }
 
 impl UiNode for NoiseUniform {
     fn add_ui(&mut self, ui: &mut egui::Ui) {
-        use std::fmt::Write;
-        let mut string_buf = self.seed.to_string();
-        ui.horizontal_centered(|ui| {
+        egui::Grid::new("noise_uniform").show(ui, |ui| {
             ui.label("Seed");
-            if ui
-                .add(egui::TextEdit::singleline(&mut string_buf))
-                .changed()
-            {
-                self.seed = string_buf.parse().unwrap_or(self.seed);
-            }
-        });
-        string_buf.clear();
-        write!(&mut string_buf, "{}", self.frequency).unwrap();
-        ui.horizontal_centered(|ui| {
+            ui.add(egui::DragValue::new(&mut self.seed).speed(0.001));
+            ui.end_row();
+
             ui.label("Frequency");
-            if ui
-                .add(egui::TextEdit::singleline(&mut string_buf))
-                .changed()
-            {
-                self.frequency = string_buf.parse().unwrap_or(self.frequency);
-            }
+            ui.add(
+                egui::DragValue::new(&mut self.frequency)
+                    .speed(0.05)
+                    .clamp_range(0.0..=f32::MAX),
+            );
+            ui.end_row();
+
+            ui.label("Offset");
+            ui.horizontal(|ui| {
+                ui.add(egui::DragValue::new(&mut self.offset[0]).speed(0.05));
+                ui.add(egui::DragValue::new(&mut self.offset[1]).speed(0.05));
+            });
+            ui.end_row();
+
+            ui.label("Mode");
+            let mut mode = FractalMode::from_u32(self.mode);
+            egui::ComboBox::from_id_source("noise_fractal_mode")
+                .selected_text(mode.name())
+                .show_ui(ui, |ui| {
+                    for option in FractalMode::ALL {
+                        ui.selectable_value(&mut mode, option, option.name());
+                    }
+                });
+            self.mode = mode as u32;
+            ui.end_row();
+
+            ui.label("Octaves");
+            ui.add(egui::DragValue::new(&mut self.octaves).clamp_range(1..=MAX_OCTAVES));
+            ui.end_row();
+
+            ui.label("Lacunarity");
+            ui.add(egui::DragValue::new(&mut self.lacunarity).speed(0.01));
+            ui.end_row();
+
+            ui.label("Persistence");
+            ui.add(egui::DragValue::new(&mut self.persistence).speed(0.01));
+            ui.end_row();
+
+            ui.label("Amplitude");
+            ui.add(egui::DragValue::new(&mut self.amplitude).speed(0.01));
+            ui.end_row();
         });
-        ui.horizontal_centered(|ui| {
-            ui.label("Offset ");
-            write!(&mut string_buf, "{}", self.offset[0]).unwrap();
-            if ui
-                .add(egui::TextEdit::singleline(&mut string_buf))
-                .changed()
-            {
-                self.offset[0] = string_buf.parse().unwrap_or(self.offset[0]);
+    }
+}
+
+/// CPU implementation of `assets/shaders/noise.wgsl`, for generating noise
+/// without a GPU.
+pub mod cpu {
+    use super::{FractalMode, FractalSettings, NoiseUniform};
+
+    fn permute(x: f32) -> f32 {
+        ((x * 34.0 + 1.0) * x) % 289.0
+    }
+
+    fn fract(x: f32) -> f32 {
+        x - x.floor()
+    }
+
+    fn fade(t: f32) -> f32 {
+        t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+    }
+
+    fn mix(a: f32, b: f32, t: f32) -> f32 {
+        a * (1.0 - t) + b * t
+    }
+
+    fn step(edge: f32, x: f32) -> f32 {
+        if x >= edge {
+            1.0
+        } else {
+            0.0
+        }
+    }
+
+    fn taylor_inv_sqrt(a: f32) -> f32 {
+        1.792_842_9 - 0.853_734_7 * a
+    }
+
+    fn dot3(a: [f32; 3], b: [f32; 3]) -> f32 {
+        a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+    }
+
+    /// Port of `perlinNoise2`.
+    pub fn perlin_noise2(x: f32, y: f32) -> f32 {
+        let (x0, y0) = (x.floor() % 289.0, y.floor() % 289.0);
+        let (x1, y1) = ((x.floor() + 1.0) % 289.0, (y.floor() + 1.0) % 289.0);
+        let (fx0, fy0) = (fract(x), fract(y));
+        let (fx1, fy1) = (fx0 - 1.0, fy0 - 1.0);
+
+        let gradient = |ix: f32, iy: f32, fx: f32, fy: f32| {
+            let i = permute(permute(ix) + iy);
+            let gx = 2.0 * fract(i * 0.024_390_243) - 1.0;
+            let gy = gx.abs() - 0.5;
+            let gx = gx - (gx + 0.5).floor();
+            let norm = taylor_inv_sqrt(gx * gx + gy * gy);
+            (gx * norm) * fx + (gy * norm) * fy
+        };
+        let n00 = gradient(x0, y0, fx0, fy0);
+        let n10 = gradient(x1, y0, fx1, fy0);
+        let n01 = gradient(x0, y1, fx0, fy1);
+        let n11 = gradient(x1, y1, fx1, fy1);
+
+        let (fade_x, fade_y) = (fade(fx0), fade(fy0));
+        let n_x0 = mix(n00, n10, fade_x);
+        let n_x1 = mix(n01, n11, fade_x);
+        2.3 * mix(n_x0, n_x1, fade_y)
+    }
+
+    /// Port of `snoise`, classic 3D Perlin noise.
+    pub fn perlin_noise3(x: f32, y: f32, z: f32) -> f32 {
+        let p = [x, y, z];
+        let pi0 = p.map(|c| c.floor() % 289.0);
+        let pi1 = p.map(|c| (c.floor() + 1.0) % 289.0);
+        let pf0 = p.map(fract);
+        let pf1 = pf0.map(|c| c - 1.0);
+
+        let gradient = |ix: f32, iy: f32, iz: f32| {
+            let i = permute(permute(permute(ix) + iy) + iz);
+            let gx = i / 7.0;
+            let mut gy = fract(gx.floor() / 7.0) - 0.5;
+            let mut gx = fract(gx);
+            let gz = 0.5 - gx.abs() - gy.abs();
+            let sz = step(gz, 0.0);
+            gx -= sz * (step(0.0, gx) - 0.5);
+            gy -= sz * (step(0.0, gy) - 0.5);
+            let g = [gx, gy, gz];
+            let norm = taylor_inv_sqrt(dot3(g, g));
+            g.map(|c| c * norm)
+        };
+
+        let corner = |cx: bool, cy: bool, cz: bool| {
+            let g = gradient(
+                if cx { pi1[0] } else { pi0[0] },
+                if cy { pi1[1] } else { pi0[1] },
+                if cz { pi1[2] } else { pi0[2] },
+            );
+            let f = [
+                if cx { pf1[0] } else { pf0[0] },
+                if cy { pf1[1] } else { pf0[1] },
+                if cz { pf1[2] } else { pf0[2] },
+            ];
+            dot3(g, f)
+        };
+
+        let fade_xyz = pf0.map(fade);
+        let n_z = [(false, false), (true, false), (false, true), (true, true)]
+            .map(|(cx, cy)| mix(corner(cx, cy, false), corner(cx, cy, true), fade_xyz[2]));
+        let n_yz = [
+            mix(n_z[0], n_z[2], fade_xyz[1]),
+            mix(n_z[1], n_z[3], fade_xyz[1]),
+        ];
+        2.2 * mix(n_yz[0], n_yz[1], fade_xyz[0])
+    }
+
+    /// Combines the octaves of `noise`, which is called with the frequency
+    /// multiplier of each octave.
+    pub fn fractal(settings: &FractalSettings, noise: impl Fn(f32) -> f32) -> f32 {
+        let mut sum = 0.0;
+        let mut amplitude = settings.amplitude;
+        let mut frequency = 1.0;
+        for _ in 0..settings.octaves {
+            let n = noise(frequency);
+            sum += match settings.mode {
+                FractalMode::Fbm => n * amplitude,
+                FractalMode::Ridged => (1.0 - n.abs()) * (1.0 - n.abs()) * amplitude,
+                FractalMode::Billow => (n.abs() * 2.0 - 1.0) * amplitude,
+            };
+            frequency *= settings.lacunarity;
+            amplitude *= settings.persistence;
+        }
+
+        sum
+    }
+
+    fn to_color_channel(value: f32) -> u8 {
+        (value.clamp(0.0, 1.0) * 255.0) as u8
+    }
+
+    /// Noise value `cm_main` computes for the pixel at `(x, y)`.
+    pub fn sample(uniform: &NoiseUniform, x: u32, y: u32) -> f32 {
+        let (width, height) = uniform.size();
+        let u = x as f32 / width as f32;
+        let v = y as f32 / height as f32;
+
+        let x = u * uniform.frequency + uniform.offset[1];
+        let y = v * uniform.frequency + uniform.offset[0];
+        let z = uniform.seed * 10000.0;
+        fractal(&uniform.fractal(), |frequency| {
+            perlin_noise3(x * frequency, y * frequency, z)
+        })
+    }
+
+    /// Row major RGBA8 image of the noise `cm_main` writes.
+    pub fn generate(uniform: &NoiseUniform) -> Vec<u8> {
+        const COLOR_MULTIPLIER: f32 = 5.5;
+        const COLOR_ADDITIVE: f32 = 0.5;
+
+        let (width, height) = uniform.size();
+        let mut image = Vec::with_capacity(uniform.image_size_in_bytes() as usize);
+        for y in 0..height {
+            for x in 0..width {
+                let noise = sample(uniform, x, y);
+                let color = to_color_channel(noise * COLOR_MULTIPLIER + COLOR_ADDITIVE);
+                image.extend_from_slice(&[color, color, color, 255]);
             }
-            write!(&mut string_buf, "{}", self.offset[1]).unwrap();
-            if ui
-                .add(egui::TextEdit::singleline(&mut string_buf))
-                .changed()
-            {
-                self.offset[1] = string_buf.parse().unwrap_or(self.offset[1]);
+        }
+
+        image
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        /// Values `assets/shaders/noise.wgsl` gives for the same inputs, read
+        /// back from the GL backend.
+        const NOISE2: [((f32, f32), f32); 5] = [
+            ((0.5, 0.5), -0.491_532_62),
+            ((1.25, -3.75), 0.230_947_1),
+            ((12.3, 45.6), 0.227_790_79),
+            ((100.1, 200.7), 0.060_955_267),
+            ((-7.4, 3.3), -0.069_190_07),
+        ];
+        const NOISE3: [((f32, f32, f32), f32); 4] = [
+            ((0.5, 0.5, 0.5), -0.625_376_4),
+            ((1.25, -3.75, 2.5), 0.415_135_4),
+            ((12.3, 45.6, 7.8), 0.076_951_59),
+            ((-7.4, 3.3, -1.1), 0.129_081_41),
+        ];
+        const FRACTAL: [(FractalMode, f32); 3] = [
+            (FractalMode::Fbm, 0.628_555_83),
+            (FractalMode::Ridged, 0.915_426_73),
+            (FractalMode::Billow, -0.615_189_2),
+        ];
+
+        fn assert_close(actual: f32, expected: f32) {
+            assert!(
+                (actual - expected).abs() < 1e-5,
+                "expected {expected}, got {actual}"
+            );
+        }
+
+        #[test]
+        fn perlin_noise2_matches_shader() {
+            for ((x, y), expected) in NOISE2 {
+                assert_close(perlin_noise2(x, y), expected);
             }
-        });
+        }
+
+        #[test]
+        fn perlin_noise3_matches_shader() {
+            for ((x, y, z), expected) in NOISE3 {
+                assert_close(perlin_noise3(x, y, z), expected);
+            }
+        }
+
+        #[test]
+        fn noise_is_zero_on_lattice_points() {
+            assert_eq!(perlin_noise2(3.0, -5.0), 0.0);
+            assert_eq!(perlin_noise3(3.0, -5.0, 7.0), 0.0);
+        }
+
+        #[test]
+        fn fractal_matches_shader() {
+            for (mode, expected) in FRACTAL {
+                let settings = FractalSettings {
+                    mode,
+                    octaves: 4,
+                    lacunarity: 2.0,
+                    persistence: 0.5,
+                    amplitude: 1.0,
+                };
+                let noise = fractal(&settings, |frequency| {
+                    perlin_noise3(1.3 * frequency, 2.7 * frequency, 0.4)
+                });
+                assert_close(noise, expected);
+            }
+        }
     }
 }
 
 pub struct NoiseGenerator {
     noise_compute_pipeline: ComputePipeline,
+    noise_bind_group_layout: BindGroupLayout,
     noise_bind_group: BindGroup,
-    noise_output_buffer: Buffer,
     noise_storage_buffer: Buffer,
     noise_uniform_buffer: Buffer,
     noise_uniform: NoiseUniform,
+    pending_reads: Vec<PendingRead>,
+}
+
+/// Copy of the noise output waiting to be mapped and written to `path`.
+struct PendingRead {
+    buffer: Buffer,
+    noise_uniform: NoiseUniform,
+    path: PathBuf,
+    /// Set once the buffer is mapped, which has to wait until the copy has
+    /// been submitted.
+    receiver: Option<Receiver<Result<(), BufferAsyncError>>>,
+    /// The thread writing the file, waiting for the read back image.
+    image_sender: Sender<Vec<u8>>,
 }
 
 impl NoiseGenerator {
@@ -143,34 +555,13 @@ impl NoiseGenerator {
             usage: BufferUsages::UNIFORM | BufferUsages::COPY_DST,
         });
 
-        let noise_output_buffer = device.create_buffer(&BufferDescriptor {
-            label: Some("noise_output_buffer"),
-            size: noise_uniform.image_size_in_bytes(),
-            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
-            mapped_at_creation: false,
-        });
-
-        let noise_storage_buffer = device.create_buffer(&BufferDescriptor {
-            label: Some("noise_output_buffer"),
-            size: noise_uniform.image_size_in_bytes(),
-            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
-            mapped_at_creation: false,
-        });
-
-        let noise_bind_group = device.create_bind_group(&BindGroupDescriptor {
-            label: Some("noise_bind_group"),
-            layout: &noise_bind_group_layout,
-            entries: &[
-                BindGroupEntry {
-                    binding: 0,
-                    resource: noise_uniform_buffer.as_entire_binding(),
-                },
-                BindGroupEntry {
-                    binding: 1,
-                    resource: noise_storage_buffer.as_entire_binding(),
-                },
-            ],
-        });
+        let noise_storage_buffer = Self::create_storage_buffer(device, &noise_uniform);
+        let noise_bind_group = Self::create_bind_group(
+            device,
+            &noise_bind_group_layout,
+            &noise_uniform_buffer,
+            &noise_storage_buffer,
+        );
 
         let noise_compute_pipeline = device.create_compute_pipeline(&ComputePipelineDescriptor {
             label: Some("noise_compute_pipeline"),
@@ -182,10 +573,11 @@ impl NoiseGenerator {
         Ok(Self {
             noise_storage_buffer,
             noise_compute_pipeline,
+            noise_bind_group_layout,
             noise_bind_group,
-            noise_output_buffer,
             noise_uniform_buffer,
             noise_uniform,
+            pending_reads: Vec::new(),
         })
     }
 
@@ -196,22 +588,15 @@ impl NoiseGenerator {
             return;
         }
 
-        let new_image_size_in_bytes = noise_uniform.image_size_in_bytes();
-        if new_image_size_in_bytes > self.noise_output_buffer.size() {
-            self.noise_output_buffer = device.create_buffer(&BufferDescriptor {
-                label: Some("noise_output_buffer"),
-                size: noise_uniform.image_size_in_bytes(),
-                usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
-                mapped_at_creation: false,
-            });
-        }
-        if new_image_size_in_bytes > self.noise_storage_buffer.size() {
-            self.noise_storage_buffer = device.create_buffer(&BufferDescriptor {
-                label: Some("noise_output_buffer"),
-                size: noise_uniform.image_size_in_bytes(),
-                usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
-                mapped_at_creation: false,
-            });
+        if noise_uniform.buffer_size_in_bytes() > self.noise_storage_buffer.size() {
+            self.noise_storage_buffer = Self::create_storage_buffer(device, &noise_uniform);
+            // The old bind group still points at the previous storage buffer.
+            self.noise_bind_group = Self::create_bind_group(
+                device,
+                &self.noise_bind_group_layout,
+                &self.noise_uniform_buffer,
+                &self.noise_storage_buffer,
+            );
         }
 
         self.noise_uniform = noise_uniform;
@@ -222,8 +607,47 @@ impl NoiseGenerator {
         );
     }
 
-    pub fn compute<'a>(&'a self, encoder: &mut CommandEncoder) {
-        const WORKGROUP_SIZE: u32 = 64;
+    fn create_output_buffer(device: &Device, noise_uniform: &NoiseUniform) -> Buffer {
+        device.create_buffer(&BufferDescriptor {
+            label: Some("noise_output_buffer"),
+            size: noise_uniform.buffer_size_in_bytes(),
+            usage: BufferUsages::MAP_READ | BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_storage_buffer(device: &Device, noise_uniform: &NoiseUniform) -> Buffer {
+        device.create_buffer(&BufferDescriptor {
+            label: Some("noise_storage_buffer"),
+            size: noise_uniform.buffer_size_in_bytes(),
+            usage: BufferUsages::STORAGE | BufferUsages::COPY_SRC,
+            mapped_at_creation: false,
+        })
+    }
+
+    fn create_bind_group(
+        device: &Device,
+        layout: &BindGroupLayout,
+        uniform_buffer: &Buffer,
+        storage_buffer: &Buffer,
+    ) -> BindGroup {
+        device.create_bind_group(&BindGroupDescriptor {
+            label: Some("noise_bind_group"),
+            layout,
+            entries: &[
+                BindGroupEntry {
+                    binding: 0,
+                    resource: uniform_buffer.as_entire_binding(),
+                },
+                BindGroupEntry {
+                    binding: 1,
+                    resource: storage_buffer.as_entire_binding(),
+                },
+            ],
+        })
+    }
+
+    pub fn compute(&self, encoder: &mut CommandEncoder) {
         let (width, height) = self.noise_uniform.size();
         {
             let mut compute_pass = encoder.begin_compute_pass(&ComputePassDescriptor {
@@ -233,38 +657,102 @@ impl NoiseGenerator {
 
             compute_pass.set_pipeline(&self.noise_compute_pipeline);
             compute_pass.set_bind_group(0, &self.noise_bind_group, &[]);
-            compute_pass.dispatch_workgroups(128, 128, 1);
-        }
-        {
-            encoder.copy_buffer_to_buffer(
-                &self.noise_storage_buffer,
-                0,
-                &self.noise_output_buffer,
-                0,
-                self.noise_uniform.image_size_in_bytes(),
-            );
+            // The shader skips the invocations past the last column.
+            compute_pass.dispatch_workgroups(width.div_ceil(WORKGROUP_SIZE), height, 1);
         }
     }
 
-    pub fn async_read(&self) {
-        self.noise_output_buffer
-            .slice(0..self.noise_uniform.image_size_in_bytes())
-            .map_async(wgpu::MapMode::Read, |result| {
-                info!("NoiseGenerator MAP READ {:?}", result);
-            });
+    /// Records a copy of the last computed noise, which gets written to `path`
+    /// once it has been read back. Call `map_pending_reads` after submitting
+    /// the encoder and `poll_pending_reads` every frame. The returned thread
+    /// finishes once the file is written or the read failed.
+    pub fn save_to_file(
+        &mut self,
+        device: &Device,
+        encoder: &mut CommandEncoder,
+        path: PathBuf,
+    ) -> JoinHandle<()> {
+        let buffer = Self::create_output_buffer(device, &self.noise_uniform);
+        encoder.copy_buffer_to_buffer(
+            &self.noise_storage_buffer,
+            0,
+            &buffer,
+            0,
+            self.noise_uniform.buffer_size_in_bytes(),
+        );
+
+        // Encoding takes a while for large images, keep it off the render thread.
+        let (image_sender, image_receiver) = mpsc::channel::<Vec<u8>>();
+        let size = self.noise_uniform.size();
+        let image_path = path.clone();
+        let writer = std::thread::spawn(move || {
+            // The sender is dropped without an image when the read fails.
+            let Ok(image) = image_receiver.recv() else {
+                return;
+            };
+            match save_image(&image_path, size, &image) {
+                Ok(()) => info!("Saved noise to {:?}", image_path),
+                Err(err) => error!("Failed to save noise to {:?}: {err}", image_path),
+            }
+        });
+
+        self.pending_reads.push(PendingRead {
+            buffer,
+            noise_uniform: self.noise_uniform,
+            path,
+            receiver: None,
+            image_sender,
+        });
+
+        writer
     }
 
-    pub fn read_and_save_to_img(&self) {
-        {
-            let view = self
-                .noise_output_buffer
-                .slice(0..self.noise_uniform.image_size_in_bytes())
-                .get_mapped_range();
+    pub fn map_pending_reads(&mut self) {
+        for read in self.pending_reads.iter_mut() {
+            if read.receiver.is_some() {
+                continue;
+            }
 
-            save_tmp_image(self.noise_uniform.size(), view.as_ref());
+            let (sender, receiver) = mpsc::channel();
+            read.buffer
+                .slice(..)
+                .map_async(wgpu::MapMode::Read, move |result| {
+                    let _ = sender.send(result);
+                });
+            read.receiver = Some(receiver);
+        }
+    }
+
+    /// Writes out the reads that finished mapping, without blocking on the
+    /// ones that haven't.
+    pub fn poll_pending_reads(&mut self, device: &Device) {
+        if self.pending_reads.is_empty() {
+            return;
         }
+        device.poll(wgpu::Maintain::Poll);
 
-        self.noise_output_buffer.unmap();
+        self.pending_reads.retain(|read| {
+            let Some(receiver) = &read.receiver else {
+                return true;
+            };
+            let result = match receiver.try_recv() {
+                Ok(result) => result,
+                Err(TryRecvError::Empty) => return true,
+                Err(TryRecvError::Disconnected) => Err(BufferAsyncError),
+            };
+            if let Err(err) = result {
+                error!("Failed to read back noise for {:?}: {err}", read.path);
+                return false;
+            }
+
+            let image = read
+                .noise_uniform
+                .unpad_rows(&read.buffer.slice(..).get_mapped_range());
+            read.buffer.unmap();
+            let _ = read.image_sender.send(image);
+
+            false
+        });
     }
 
     pub fn copy_to_texture(&self, encoder: &mut CommandEncoder, texture: &Texture) {
@@ -274,7 +762,7 @@ impl NoiseGenerator {
                 buffer: &self.noise_storage_buffer,
                 layout: wgpu::ImageDataLayout {
                     offset: 0,
-                    bytes_per_row: Some(width * std::mem::size_of::<u8>() as u32 * 4),
+                    bytes_per_row: Some(self.noise_uniform.padded_bytes_per_row()),
                     rows_per_image: Some(height),
                 },
             },
@@ -287,3 +775,81 @@ impl NoiseGenerator {
         );
     }
 }
+
+pub fn save_image(path: &Path, size: (u32, u32), data: &[u8]) -> image::ImageResult<()> {
+    image::save_buffer_with_format(
+        path,
+        data,
+        size.0,
+        size.1,
+        image::ExtendedColorType::Rgba8,
+        image::ImageFormat::Png,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Noise saved at `size` the way `State::generate_noise` saves it.
+    async fn save_noise(device: &Device, queue: &Queue, size: (u32, u32)) -> image::RgbaImage {
+        let uniform = NoiseUniform::new(0.25, 5.0, (0.0, 0.0), size);
+        let mut generator = NoiseGenerator::new(device, uniform).await.unwrap();
+        let path = std::env::temp_dir().join(format!("lotus-noise-{}x{}.png", size.0, size.1));
+        let _ = std::fs::remove_file(&path);
+
+        let mut encoder = device.create_command_encoder(&Default::default());
+        generator.compute(&mut encoder);
+        let writer = generator.save_to_file(device, &mut encoder, path.clone());
+        queue.submit(Some(encoder.finish()));
+        generator.map_pending_reads();
+        device.poll(wgpu::Maintain::Wait);
+        generator.poll_pending_reads(device);
+
+        writer.join().unwrap();
+        let image = image::open(&path).unwrap().to_rgba8();
+        let _ = std::fs::remove_file(&path);
+        image
+    }
+
+    #[test]
+    fn saved_noise_has_no_row_padding() {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        runtime.block_on(async {
+            let instance = wgpu::Instance::default();
+            let Some(adapter) = instance.request_adapter(&Default::default()).await else {
+                eprintln!("No adapter, skipping");
+                return;
+            };
+            let (device, queue) = adapter
+                .request_device(&Default::default(), None)
+                .await
+                .unwrap();
+
+            // Widths below, at and past a workgroup and the row alignment.
+            for size in [
+                (1, 1),
+                (7, 3),
+                (63, 2),
+                (64, 64),
+                (65, 5),
+                (100, 9),
+                (300, 4),
+            ] {
+                let uniform = NoiseUniform::new(0.25, 5.0, (0.0, 0.0), size);
+                let image = save_noise(&device, &queue, size).await;
+
+                assert_eq!(image.dimensions(), size);
+                assert_eq!(image.as_raw().len() as u64, uniform.image_size_in_bytes());
+                // Padding would shift the rows, so compare every pixel.
+                let expected = cpu::generate(&uniform);
+                for (index, (gpu, cpu)) in image.as_raw().iter().zip(&expected).enumerate() {
+                    assert!(
+                        gpu.abs_diff(*cpu) <= 1,
+                        "{size:?} byte {index}: gpu {gpu}, cpu {cpu}"
+                    );
+                }
+            }
+        });
+    }
+}
@@ -0,0 +1,187 @@
+use std::{
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener},
+    path::PathBuf,
+    str::FromStr,
+    sync::mpsc,
+};
+
+use anyhow::Context;
+use log::{info, warn};
+use winit::event_loop::EventLoopProxy;
+
+use crate::CustomEvents;
+
+/// What a remote command printed, or its error.
+pub type CommandReply = Result<String, String>;
+
+/// Where the remote console listens, `host:port` or `unix:/path/to/socket`.
+#[derive(Debug, Clone)]
+pub enum ListenAddress {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+}
+
+impl FromStr for ListenAddress {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(path) = s.strip_prefix("unix:") {
+            #[cfg(unix)]
+            return Ok(ListenAddress::Unix(PathBuf::from(path)));
+            #[cfg(not(unix))]
+            return Err(format!("Unix sockets aren't supported here: {path}"));
+        }
+        s.parse()
+            .map(ListenAddress::Tcp)
+            .map_err(|err| format!("'{s}' is not host:port or unix:path, {err}"))
+    }
+}
+
+/// Returned by `listen`, removes the unix socket file once dropped so exiting
+/// doesn't leave it behind.
+#[must_use = "dropping the listener removes its socket file"]
+#[derive(Debug, Default)]
+pub struct Listener {
+    #[cfg(unix)]
+    socket: Option<PathBuf>,
+}
+
+impl Drop for Listener {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(path) = &self.socket {
+            if let Err(err) = std::fs::remove_file(path) {
+                warn!("Failed to remove socket {:?}: {err}", path);
+            }
+        }
+    }
+}
+
+/// Starts accepting connections in the background. Every line a client
+/// sends runs as a console command, see `serve` for the replies.
+pub fn listen(
+    address: &ListenAddress,
+    proxy: EventLoopProxy<CustomEvents>,
+) -> anyhow::Result<Listener> {
+    match address {
+        ListenAddress::Tcp(address) => {
+            let listener = TcpListener::bind(address)
+                .with_context(|| format!("Failed to listen on {address}"))?;
+            if !address.ip().is_loopback() {
+                warn!("Remote console on {address} runs commands from anyone who can reach it");
+            }
+            info!("Remote console listening on {address}");
+            std::thread::spawn(move || {
+                accept(
+                    listener.incoming(),
+                    |stream| stream.try_clone(),
+                    |stream| match stream.peer_addr() {
+                        Ok(peer) => peer.to_string(),
+                        Err(_) => "unknown".into(),
+                    },
+                    proxy,
+                )
+            });
+            Ok(Listener::default())
+        }
+        #[cfg(unix)]
+        ListenAddress::Unix(path) => {
+            use std::os::unix::{fs::FileTypeExt, net::UnixListener};
+
+            // A socket left behind by an earlier run would fail the bind.
+            let stale = std::fs::symlink_metadata(path)
+                .is_ok_and(|metadata| metadata.file_type().is_socket());
+            if stale {
+                std::fs::remove_file(path)
+                    .with_context(|| format!("Failed to remove old socket {:?}", path))?;
+            }
+            let listener = UnixListener::bind(path)
+                .with_context(|| format!("Failed to listen on {:?}", path))?;
+            info!("Remote console listening on {:?}", path);
+            let peer = path.display().to_string();
+            std::thread::spawn(move || {
+                accept(
+                    listener.incoming(),
+                    |stream| stream.try_clone(),
+                    move |_| peer.clone(),
+                    proxy,
+                )
+            });
+            Ok(Listener {
+                socket: Some(path.clone()),
+            })
+        }
+    }
+}
+
+/// Runs `command` in the event loop and waits for its reply, `None` once the
+/// engine has exited.
+pub fn send(proxy: &EventLoopProxy<CustomEvents>, command: String) -> Option<CommandReply> {
+    let (reply, replies) = mpsc::channel();
+    proxy
+        .send_event(CustomEvents::RemoteCommand { command, reply })
+        .ok()?;
+    // The sender is dropped without a reply when the engine exits.
+    replies.recv().ok()
+}
+
+/// Serves every client on its own thread.
+fn accept<S: Read + Write + Send + 'static>(
+    incoming: impl Iterator<Item = std::io::Result<S>>,
+    try_clone: impl Fn(&S) -> std::io::Result<S>,
+    peer: impl Fn(&S) -> String,
+    proxy: EventLoopProxy<CustomEvents>,
+) {
+    for stream in incoming {
+        let (reader, writer) = match stream.and_then(|stream| Ok((try_clone(&stream)?, stream))) {
+            Ok(streams) => streams,
+            Err(err) => {
+                warn!("Remote console connection failed: {err}");
+                continue;
+            }
+        };
+        let peer = peer(&writer);
+        let proxy = proxy.clone();
+        std::thread::spawn(move || serve(&peer, BufReader::new(reader), writer, proxy));
+    }
+}
+
+/// Runs the lines of one client until it disconnects or the engine exits.
+/// Each reply is the command's output with every line prefixed by `| `,
+/// followed by a line with `ok`, or `error` if the command failed.
+fn serve(
+    peer: &str,
+    reader: impl BufRead,
+    mut writer: impl Write,
+    proxy: EventLoopProxy<CustomEvents>,
+) {
+    info!("Remote console client {peer} connected");
+    for line in reader.lines() {
+        let Ok(command) = line else {
+            break;
+        };
+        if command.trim().is_empty() {
+            continue;
+        }
+
+        let (text, status) = match send(&proxy, command) {
+            Some(Ok(output)) => (output, "ok"),
+            Some(Err(err)) => (err, "error"),
+            None => break,
+        };
+        let mut response = String::new();
+        for line in text.lines() {
+            response += "| ";
+            response += line;
+            response += "\n";
+        }
+        response += status;
+        response += "\n";
+        if writer.write_all(response.as_bytes()).is_err() {
+            break;
+        }
+    }
+    info!("Remote console client {peer} disconnected");
+}
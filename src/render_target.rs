@@ -0,0 +1,188 @@
+use std::{
+    path::Path,
+    sync::{mpsc, Arc},
+};
+
+use anyhow::Context;
+use wgpu::{
+    Device, Extent3d, ImageCopyBuffer, Queue, Surface, SurfaceConfiguration, SurfaceError,
+    SurfaceTexture, TextureFormat, TextureView,
+};
+use winit::window::Window;
+
+use crate::{engine_state::TextureWithView, noise::save_image};
+
+/// Format of offscreen targets, the same bytes end up in frame dumps.
+pub const OFFSCREEN_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
+/// Where frames get rendered, a window surface or a texture when running
+/// headless.
+pub enum RenderTarget {
+    Window {
+        window: Arc<Window>,
+        surface: Surface<'static>,
+        config: SurfaceConfiguration,
+    },
+    Offscreen {
+        texture: TextureWithView,
+    },
+}
+
+/// Color target of a single frame, `present` it once rendering is done.
+pub struct Frame {
+    pub view: TextureView,
+    surface_texture: Option<SurfaceTexture>,
+}
+
+impl Frame {
+    pub fn present(self) {
+        if let Some(surface_texture) = self.surface_texture {
+            surface_texture.present();
+        }
+    }
+}
+
+impl RenderTarget {
+    pub fn offscreen(device: &Device, size: (u32, u32)) -> Self {
+        assert!(size.0 != 0 && size.1 != 0);
+        let texture = TextureWithView::create("offscreen".into(), size, OFFSCREEN_FORMAT, device);
+
+        Self::Offscreen { texture }
+    }
+
+    pub fn window(&self) -> Option<&Arc<Window>> {
+        match self {
+            RenderTarget::Window { window, .. } => Some(window),
+            RenderTarget::Offscreen { .. } => None,
+        }
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        match self {
+            RenderTarget::Window { config, .. } => (config.width, config.height),
+            RenderTarget::Offscreen { texture } => {
+                let size = texture.texture.size();
+                (size.width, size.height)
+            }
+        }
+    }
+
+    pub fn format(&self) -> TextureFormat {
+        match self {
+            RenderTarget::Window { config, .. } => config.format,
+            RenderTarget::Offscreen { texture } => texture.texture.format(),
+        }
+    }
+
+    pub fn resize(&mut self, device: &Device, size: (u32, u32)) {
+        match self {
+            RenderTarget::Window {
+                surface, config, ..
+            } => {
+                config.width = size.0;
+                config.height = size.1;
+                surface.configure(device, config);
+            }
+            RenderTarget::Offscreen { texture } => {
+                *texture = TextureWithView::create(
+                    "offscreen".into(),
+                    size,
+                    texture.texture.format(),
+                    device,
+                );
+            }
+        }
+    }
+
+    pub fn current_frame(&self) -> Result<Frame, SurfaceError> {
+        match self {
+            RenderTarget::Window { surface, .. } => {
+                let surface_texture = surface.get_current_texture()?;
+                let view = surface_texture
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default());
+
+                Ok(Frame {
+                    view,
+                    surface_texture: Some(surface_texture),
+                })
+            }
+            RenderTarget::Offscreen { texture } => Ok(Frame {
+                view: texture
+                    .texture
+                    .create_view(&wgpu::TextureViewDescriptor::default()),
+                surface_texture: None,
+            }),
+        }
+    }
+
+    /// Reads back the last rendered frame as tightly packed RGBA8, blocking
+    /// until the GPU is done. Only offscreen targets can be read.
+    pub fn read_frame(&self, device: &Device, queue: &Queue) -> anyhow::Result<Vec<u8>> {
+        let RenderTarget::Offscreen { texture } = self else {
+            anyhow::bail!("Only offscreen render targets can be read back");
+        };
+
+        let (width, height) = self.size();
+        let row_size = width * 4;
+        let padded_row_size = row_size.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("frame_readback_buffer"),
+            size: padded_row_size as u64 * height as u64,
+            usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Frame Readback Encoder"),
+        });
+        encoder.copy_texture_to_buffer(
+            texture.texture.as_image_copy(),
+            ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_size),
+                    rows_per_image: Some(height),
+                },
+            },
+            Extent3d {
+                width,
+                height,
+                depth_or_array_layers: 1,
+            },
+        );
+        queue.submit(std::iter::once(encoder.finish()));
+
+        let (sender, receiver) = mpsc::channel();
+        buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        device.poll(wgpu::Maintain::Wait);
+        receiver
+            .recv()
+            .context("Frame readback was dropped")?
+            .context("Failed to map frame readback buffer")?;
+
+        let mut frame = Vec::with_capacity((row_size * height) as usize);
+        {
+            let data = buffer.slice(..).get_mapped_range();
+            for row in data.chunks(padded_row_size as usize) {
+                frame.extend_from_slice(&row[..row_size as usize]);
+            }
+        }
+        buffer.unmap();
+
+        Ok(frame)
+    }
+
+    pub fn save_frame(&self, device: &Device, queue: &Queue, path: &Path) -> anyhow::Result<()> {
+        let frame = self.read_frame(device, queue)?;
+        save_image(path, self.size(), &frame)
+            .with_context(|| format!("Failed to save frame to {path:?}"))?;
+
+        Ok(())
+    }
+}
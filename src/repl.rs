@@ -0,0 +1,55 @@
+use log::info;
+use winit::event_loop::EventLoopProxy;
+
+use crate::{
+    commands::Command,
+    remote_console::{self, CommandReply},
+    state::State,
+    CustomEvents,
+};
+
+/// Reads commands from stdin on a background thread and runs them in the
+/// event loop, printing their output to stdout.
+pub fn spawn(proxy: EventLoopProxy<CustomEvents>) {
+    std::thread::spawn(move || {
+        for line in std::io::stdin().lines() {
+            let Ok(command) = line else {
+                break;
+            };
+            if command.trim().is_empty() {
+                continue;
+            }
+            match remote_console::send(&proxy, command) {
+                Some(reply) => print_reply(reply),
+                None => break,
+            }
+        }
+    });
+}
+
+/// Runs the commands from stdin right away until the input ends or `exit`,
+/// headless states have no event loop to send them to. Blocks the caller
+/// until then, the headless frame is only rendered and written afterwards.
+pub fn run(state: &mut State) {
+    info!("Reading commands from stdin, the frame is saved once it closes");
+    for line in std::io::stdin().lines() {
+        let Ok(command) = line else {
+            break;
+        };
+        match command.trim() {
+            "" => continue,
+            "exit" => break,
+            _ => {}
+        }
+        let reply = Command::command_to_state(&command, state);
+        print_reply(reply.map_err(|err| err.to_string()));
+    }
+}
+
+fn print_reply(reply: CommandReply) {
+    match reply {
+        Ok(output) if output.is_empty() => {}
+        Ok(output) => println!("{output}"),
+        Err(err) => eprintln!("error: {err}"),
+    }
+}
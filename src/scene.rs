@@ -1,11 +1,137 @@
-use wgpu::{ComputePipeline, RenderPipeline, Sampler, Texture};
+use std::collections::HashMap;
 
-use crate::{ecs::ecs::Res, engine_state::TextureWithView};
+use wgpu::{
+    util::DeviceExt, BindGroup, ComputePipeline, Device, Queue, RenderPass, RenderPipeline, Sampler,
+};
 
+use crate::{
+    ecs::{
+        ecs::Res,
+        world::{Entity, World},
+    },
+    engine_state::{EngineError, TextureWithView},
+    voxel::{
+        instance::{Instance, InstanceRaw},
+        model::{Material, Mesh},
+    },
+};
+
+/// Name of a scene entity, what console commands refer to it by.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Name(pub String);
+
+/// Meshes entities can be spawned with, and the instance buffer the world's
+/// scene entities are drawn from.
 #[derive(Default)]
 pub struct Scene {
     pub textures: Vec<Res<TextureWithView>>,
     pub samples: Vec<Res<Sampler>>,
     pub render_pipelines: Vec<Res<RenderPipeline>>,
     pub compute_pipelines: Vec<Res<ComputePipeline>>,
+
+    meshes: HashMap<String, Res<Mesh>>,
+    draw_list: Vec<(Res<Mesh>, Res<Material>)>,
+    /// What was last written to `instance_buffer`.
+    instance_data: Vec<InstanceRaw>,
+    instance_buffer: Option<wgpu::Buffer>,
+}
+
+impl Scene {
+    pub fn register_mesh(&mut self, name: String, mesh: Res<Mesh>) -> Result<(), EngineError> {
+        if self.meshes.contains_key(&name) {
+            return Err(EngineError::NameAlreadyExists);
+        }
+        self.meshes.insert(name, mesh);
+
+        Ok(())
+    }
+
+    /// Spawns an entity drawn with the mesh `mesh_name` into `world`.
+    pub fn spawn(
+        &self,
+        world: &mut World,
+        name: String,
+        mesh_name: &str,
+        transform: Instance,
+    ) -> Result<Entity, EngineError> {
+        if find_entity(world, &name).is_some() {
+            return Err(EngineError::NameAlreadyExists);
+        }
+        let mesh = self
+            .meshes
+            .get(mesh_name)
+            .cloned()
+            .ok_or_else(|| EngineError::NameNotFound(mesh_name.into()))?;
+        let material = mesh.material.clone();
+
+        Ok(world.spawn((Name(name), transform, mesh, material)))
+    }
+
+    pub fn mesh_names(&self) -> impl Iterator<Item = &str> {
+        self.meshes.keys().map(String::as_str)
+    }
+
+    /// Writes the transforms of the world's scene entities to the instance
+    /// buffer, if any changed.
+    pub fn update_instances(&mut self, world: &World, device: &Device, queue: &Queue) {
+        let mut draw_list = Vec::new();
+        let mut instance_data = Vec::new();
+        for (_, (transform, mesh, material)) in
+            world.query::<(&Instance, &Res<Mesh>, &Res<Material>)>()
+        {
+            instance_data.push(transform.to_raw());
+            draw_list.push((mesh.clone(), material.clone()));
+        }
+        self.draw_list = draw_list;
+        let contents: &[u8] = bytemuck::cast_slice(&instance_data);
+        if contents == bytemuck::cast_slice::<_, u8>(&self.instance_data) {
+            return;
+        }
+
+        match &self.instance_buffer {
+            Some(buffer) if buffer.size() >= contents.len() as u64 => {
+                queue.write_buffer(buffer, 0, contents);
+            }
+            _ if contents.is_empty() => {}
+            _ => {
+                self.instance_buffer = Some(device.create_buffer_init(
+                    &wgpu::util::BufferInitDescriptor {
+                        label: Some("Scene Instance Buffer"),
+                        contents,
+                        usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+                    },
+                ));
+            }
+        }
+        self.instance_data = instance_data;
+    }
+
+    pub fn draw<'a>(
+        &'a self,
+        rp: &mut RenderPass<'a>,
+        camera_bind_group: &'a BindGroup,
+        light_bind_group: &'a BindGroup,
+    ) {
+        let Some(instance_buffer) = &self.instance_buffer else {
+            return;
+        };
+        rp.set_vertex_buffer(1, instance_buffer.slice(..));
+        for (idx, (mesh, material)) in self.draw_list.iter().enumerate() {
+            let idx = idx as u32;
+            mesh.draw_with_material(
+                material,
+                idx..idx + 1,
+                rp,
+                camera_bind_group,
+                light_bind_group,
+            );
+        }
+    }
+}
+
+/// The scene entity called `name`.
+pub fn find_entity(world: &World, name: &str) -> Option<Entity> {
+    world
+        .query::<&Name>()
+        .find_map(|(entity, entity_name)| (entity_name.0 == name).then_some(entity))
 }
@@ -0,0 +1,267 @@
+use std::{collections::HashMap, time::Duration};
+
+use log::error;
+
+use crate::ecs::{
+    resources::{ResourceError, Resources},
+    world::World,
+};
+
+/// Runs once a frame in its stage, with the frame time. A system that can't
+/// get a resource is skipped for that frame.
+pub type SystemFn = fn(&mut World, &Resources, Duration) -> Result<(), ResourceError>;
+
+/// Parts of a frame, run in this order. `Input`, `Update` and `PreRender`
+/// run in `State::update`. `Render` systems draw into the frame's
+/// `Frame` and `CommandEncoder` resources, `PostRender` runs once it has
+/// been submitted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Stage {
+    Input,
+    Update,
+    PreRender,
+    Render,
+    PostRender,
+}
+
+pub struct System {
+    name: String,
+    run: SystemFn,
+    after: Vec<String>,
+    before: Vec<String>,
+}
+
+impl System {
+    pub fn new(name: &str, run: SystemFn) -> Self {
+        Self {
+            name: name.into(),
+            run,
+            after: Vec::new(),
+            before: Vec::new(),
+        }
+    }
+
+    /// Runs after the system `name` when both are in the same stage.
+    pub fn after(mut self, name: &str) -> Self {
+        self.after.push(name.into());
+        self
+    }
+
+    /// Runs before the system `name` when both are in the same stage.
+    pub fn before(mut self, name: &str) -> Self {
+        self.before.push(name.into());
+        self
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ScheduleError {
+    #[error("System '{0}' already exists")]
+    Duplicate(String),
+    #[error("Ordering system '{0}' would make a cycle")]
+    Cycle(String),
+}
+
+/// Systems of every stage, kept in the order they run.
+#[derive(Default)]
+pub struct Schedule {
+    stages: HashMap<Stage, Vec<System>>,
+}
+
+impl Schedule {
+    /// Adds `system` to `stage`. Ordering constraints on systems that aren't
+    /// in the stage yet apply once they're added.
+    pub fn add(&mut self, stage: Stage, system: System) -> Result<(), ScheduleError> {
+        if self
+            .stages
+            .values()
+            .flatten()
+            .any(|other| other.name == system.name)
+        {
+            return Err(ScheduleError::Duplicate(system.name));
+        }
+
+        let systems = self.stages.entry(stage).or_default();
+        systems.push(system);
+        let Some(order) = run_order(systems) else {
+            let system = systems.pop().unwrap();
+            return Err(ScheduleError::Cycle(system.name));
+        };
+        let mut unordered: Vec<Option<System>> = systems.drain(..).map(Some).collect();
+        systems.extend(
+            order
+                .into_iter()
+                .filter_map(|index| unordered[index].take()),
+        );
+
+        Ok(())
+    }
+
+    /// Runs the systems of `stage` in order, logging the ones that fail.
+    pub fn run(&self, stage: Stage, world: &mut World, resources: &Resources, dt: Duration) {
+        for system in self.stages.get(&stage).into_iter().flatten() {
+            if let Err(err) = (system.run)(world, resources, dt) {
+                error!("Skipped system '{}': {err}", system.name);
+            }
+        }
+    }
+}
+
+/// Indices of `systems` so each runs after those it has to follow, `None`
+/// if they can't be ordered. Unconstrained systems keep their order.
+fn run_order(systems: &[System]) -> Option<Vec<usize>> {
+    let index_of = |name: &String| systems.iter().position(|system| &system.name == name);
+    // Systems each one has to run after.
+    let mut follows = vec![Vec::new(); systems.len()];
+    for (index, system) in systems.iter().enumerate() {
+        follows[index].extend(system.after.iter().filter_map(index_of));
+        for before in system.before.iter().filter_map(index_of) {
+            follows[before].push(index);
+        }
+    }
+
+    let mut done = vec![false; systems.len()];
+    let mut order = Vec::with_capacity(systems.len());
+    while order.len() < systems.len() {
+        let next = (0..systems.len())
+            .find(|&index| !done[index] && follows[index].iter().all(|&other| done[other]))?;
+        done[next] = true;
+        order.push(next);
+    }
+
+    Some(order)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Names of the systems that ran, in order.
+    type Ran = Vec<&'static str>;
+
+    fn record(resources: &Resources, name: &'static str) -> Result<(), ResourceError> {
+        resources.get_mut::<Ran>()?.push(name);
+        Ok(())
+    }
+
+    fn a(_: &mut World, resources: &Resources, _: Duration) -> Result<(), ResourceError> {
+        record(resources, "a")
+    }
+
+    fn b(_: &mut World, resources: &Resources, _: Duration) -> Result<(), ResourceError> {
+        record(resources, "b")
+    }
+
+    fn c(_: &mut World, resources: &Resources, _: Duration) -> Result<(), ResourceError> {
+        record(resources, "c")
+    }
+
+    fn d(_: &mut World, resources: &Resources, _: Duration) -> Result<(), ResourceError> {
+        record(resources, "d")
+    }
+
+    /// Holds `Ran` while recording, so it fails.
+    fn holds_ran(_: &mut World, resources: &Resources, _: Duration) -> Result<(), ResourceError> {
+        let _ran = resources.get::<Ran>()?;
+        record(resources, "holds_ran")
+    }
+
+    fn missing(_: &mut World, resources: &Resources, _: Duration) -> Result<(), ResourceError> {
+        resources.get::<u32>()?;
+        record(resources, "missing")
+    }
+
+    fn run(schedule: &Schedule, stage: Stage) -> Ran {
+        let mut resources = Resources::default();
+        resources.insert(Ran::new());
+        schedule.run(stage, &mut World::default(), &resources, Duration::ZERO);
+        resources.remove::<Ran>().unwrap()
+    }
+
+    #[test]
+    fn keeps_the_order_systems_were_added_in() {
+        let mut schedule = Schedule::default();
+        schedule.add(Stage::Update, System::new("b", b)).unwrap();
+        schedule.add(Stage::Update, System::new("a", a)).unwrap();
+        schedule.add(Stage::Update, System::new("c", c)).unwrap();
+
+        assert_eq!(run(&schedule, Stage::Update), ["b", "a", "c"]);
+    }
+
+    #[test]
+    fn runs_after_and_before() {
+        let mut schedule = Schedule::default();
+        schedule
+            .add(Stage::Update, System::new("a", a).after("b"))
+            .unwrap();
+        schedule.add(Stage::Update, System::new("b", b)).unwrap();
+        schedule
+            .add(Stage::Update, System::new("c", c).before("b"))
+            .unwrap();
+        schedule
+            .add(Stage::Update, System::new("d", d).after("c").before("a"))
+            .unwrap();
+
+        assert_eq!(run(&schedule, Stage::Update), ["c", "b", "d", "a"]);
+    }
+
+    #[test]
+    fn orders_only_within_a_stage() {
+        let mut schedule = Schedule::default();
+        schedule
+            .add(Stage::PreRender, System::new("a", a).after("b"))
+            .unwrap();
+        schedule.add(Stage::Update, System::new("b", b)).unwrap();
+
+        assert_eq!(run(&schedule, Stage::Update), ["b"]);
+        assert_eq!(run(&schedule, Stage::PreRender), ["a"]);
+        assert!(run(&schedule, Stage::Render).is_empty());
+    }
+
+    #[test]
+    fn rejects_duplicate_names() {
+        let mut schedule = Schedule::default();
+        schedule.add(Stage::Update, System::new("a", a)).unwrap();
+
+        let same_stage = schedule.add(Stage::Update, System::new("a", b));
+        assert!(matches!(same_stage, Err(ScheduleError::Duplicate(name)) if name == "a"));
+        let other_stage = schedule.add(Stage::Render, System::new("a", b));
+        assert!(matches!(other_stage, Err(ScheduleError::Duplicate(_))));
+        assert_eq!(run(&schedule, Stage::Update), ["a"]);
+        assert!(run(&schedule, Stage::Render).is_empty());
+    }
+
+    #[test]
+    fn rejects_cycles_and_keeps_the_schedule() {
+        let mut schedule = Schedule::default();
+        schedule.add(Stage::Update, System::new("a", a)).unwrap();
+        schedule
+            .add(Stage::Update, System::new("b", b).after("a"))
+            .unwrap();
+
+        let cycle = schedule.add(Stage::Update, System::new("c", c).after("b").before("a"));
+        assert!(matches!(cycle, Err(ScheduleError::Cycle(name)) if name == "c"));
+        let itself = schedule.add(Stage::Update, System::new("d", d).after("d"));
+        assert!(matches!(itself, Err(ScheduleError::Cycle(_))));
+        assert_eq!(run(&schedule, Stage::Update), ["a", "b"]);
+
+        // The rejected names can be added again.
+        schedule.add(Stage::Update, System::new("c", c)).unwrap();
+        assert_eq!(run(&schedule, Stage::Update), ["a", "b", "c"]);
+    }
+
+    #[test]
+    fn skips_systems_that_fail() {
+        let mut schedule = Schedule::default();
+        schedule.add(Stage::Update, System::new("a", a)).unwrap();
+        schedule
+            .add(Stage::Update, System::new("holds_ran", holds_ran))
+            .unwrap();
+        schedule
+            .add(Stage::Update, System::new("missing", missing))
+            .unwrap();
+        schedule.add(Stage::Update, System::new("b", b)).unwrap();
+
+        assert_eq!(run(&schedule, Stage::Update), ["a", "b"]);
+    }
+}
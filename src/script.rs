@@ -0,0 +1,105 @@
+use std::path::{Path, PathBuf};
+
+use clap::ValueEnum;
+use log::{info, warn};
+
+use crate::{
+    commands::{Command, CommandError},
+    state::State,
+};
+
+/// Scripts can `exec` other scripts, this stops one that runs itself.
+const MAX_SCRIPT_DEPTH: usize = 16;
+
+/// What a script does when one of its commands fails.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum)]
+pub enum OnError {
+    #[default]
+    Stop,
+    Continue,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ScriptError {
+    #[error("Failed to read script {path:?}: {error}")]
+    Read {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    #[error("{path:?} line {line}: {error}")]
+    Command {
+        path: PathBuf,
+        line: usize,
+        error: CommandError,
+    },
+    #[error("Scripts nested deeper than {MAX_SCRIPT_DEPTH}")]
+    TooDeep,
+}
+
+/// Commands a finished script ran.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct ScriptReport {
+    pub executed: usize,
+    pub failed: usize,
+}
+
+/// Runs the `lotus ...` commands in the file at `path` in order, as if they
+/// were typed into the console. Blank lines and lines starting with `#` are
+/// skipped.
+pub fn run_script(
+    path: &Path,
+    on_error: OnError,
+    state: &mut State,
+) -> Result<ScriptReport, ScriptError> {
+    let script = std::fs::read_to_string(path).map_err(|error| ScriptError::Read {
+        path: path.to_owned(),
+        error,
+    })?;
+
+    if state.script_depth >= MAX_SCRIPT_DEPTH {
+        return Err(ScriptError::TooDeep);
+    }
+    state.script_depth += 1;
+    let result = run_lines(path, &script, on_error, state);
+    state.script_depth -= 1;
+
+    result
+}
+
+fn run_lines(
+    path: &Path,
+    script: &str,
+    on_error: OnError,
+    state: &mut State,
+) -> Result<ScriptReport, ScriptError> {
+    let mut report = ScriptReport::default();
+    for (number, line) in script.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        report.executed += 1;
+        match Command::command_to_state(line, state) {
+            Ok(output) => info!("{output}"),
+            Err(error) => {
+                report.failed += 1;
+                let err = ScriptError::Command {
+                    path: path.to_owned(),
+                    line: number + 1,
+                    error,
+                };
+                match on_error {
+                    OnError::Stop => return Err(err),
+                    OnError::Continue => warn!("{err}"),
+                }
+            }
+        }
+    }
+    info!(
+        "Ran {:?}, {} commands, {} failed",
+        path, report.executed, report.failed
+    );
+
+    Ok(report)
+}
@@ -1,129 +1,112 @@
-use std::time::{Duration, Instant};
+use std::{
+    cell::{Ref, RefMut},
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use anyhow::Context;
 use cgmath::{Array, Deg, Point3, Quaternion, Rotation3, Vector3};
 use log::{error, info, warn};
-use wgpu::{util::DeviceExt, Device, PolygonMode, Queue, RenderPipeline, TextureFormat};
+use wgpu::{util::DeviceExt, Device, PolygonMode, Queue, TextureFormat};
 use winit::{
     dpi::PhysicalPosition,
     event::{ElementState, WindowEvent},
     event_loop::EventLoopProxy,
-    keyboard::{KeyCode, PhysicalKey},
+    keyboard::PhysicalKey,
     window::Window,
 };
 
 use crate::{
+    app_config::{AppConfig, ConfigFile, GraphicsConfig, KeyAction},
     camera::{Camera, CameraController, CameraUniform, Projection},
-    ecs::ecs::Res,
-    engine_state::{EngineState, TextureWithView},
-    noise::{NoiseGenerator, NoiseUniform},
-    scene::Scene,
-    ui::{
-        console::ConsoleNode,
-        renderer::{UiNode, UiRenderer},
-        settings::SettingsNode,
-        text::DebugOverlay,
-    },
+    console_log::ConsoleLog,
+    cvars::{Cvar, CvarError, CvarRegistry, CvarValue},
+    ecs::{ecs::Res, resources::Resources, world::World},
+    engine_state::{supported_polygon_mode, EngineError, EngineState, TextureWithView},
+    noise::{NoiseGenerator, NoiseUniform, MAX_OCTAVES},
+    render_target::{Frame, RenderTarget},
+    scene::{self, Name, Scene},
+    schedule::{Schedule, Stage},
+    systems::{engine_schedule, CameraBinding, LightBinding, NoisePreview, Panels, ScenePass},
+    ui::{console::ConsoleNode, renderer::UiRenderer, settings::SettingsNode},
     voxel::{
+        chunk::{ChunkPos, CHUNK_SIZE},
         instance::{Instance, InstanceRaw, INSTANCE_DISPLACEMENT, NUM_INSTANCES_PER_ROW},
         light::LightUniform,
-        model::{Material, Model},
+        model::{Material, Mesh, Model},
         plane::Plane,
+        renderer::Renderer as VoxelRenderer,
+        terrain::{TerrainConfig, TerrainGenerator},
         texture,
         vertex::{ModelVertex, PrimitiveRenderer, Vertex},
+        world::VoxelWorld,
     },
     CustomEvents,
 };
 
-pub struct State<'window> {
-    scene: Scene,
-    // engine_state: EngineState,
-    wireframe_render_pipeline: RenderPipeline,
-    render_pipeline: RenderPipeline,
-    plane_renderer: PrimitiveRenderer,
+/// Everything the engine is launched with, besides where it renders to.
+pub struct Startup {
+    /// Graphics settings of this run, the config file ones with the command
+    /// line overrides applied.
+    pub graphics: GraphicsConfig,
+    pub config_file: ConfigFile,
+    pub terrain: TerrainConfig,
+    /// Log records shown in the console.
+    pub console_log: ConsoleLog,
+}
 
-    window: &'window Window,
-    device: Device,
-    queue: Queue,
+pub struct State {
+    engine_state: EngineState,
+    terrain_generator: TerrainGenerator,
 
-    default_material: Res<Material>,
+    target: RenderTarget,
+    device: Res<Device>,
+    queue: Res<Queue>,
 
-    settings: SettingsNode,
-    show_settings: bool,
-    ui_renderer: UiRenderer,
+    default_material: Res<Material>,
 
-    pub console_node: ConsoleNode,
-    show_console: bool,
+    /// Scripts currently running, `exec` inside a script nests them.
+    pub script_depth: usize,
 
     delta: Duration,
-    surface: wgpu::Surface<'window>,
-
-    size: winit::dpi::PhysicalSize<u32>,
-    config: wgpu::SurfaceConfiguration,
-
-    camera: Camera,
-    projection: Projection,
-    pub camera_controller: CameraController,
-    camera_uniform: CameraUniform,
-    camera_buffer: wgpu::Buffer,
-    camera_bind_group: wgpu::BindGroup,
 
     instances: Vec<Instance>,
     instance_buffer: wgpu::Buffer,
 
-    depth_texture: Res<TextureWithView>,
-
     obj_model: Res<Model>,
-    light_buffer: wgpu::Buffer,
-    light_uniform: LightUniform,
-    light_bind_group: wgpu::BindGroup,
 
     last_draw_call_ts: Instant,
-    proxy: EventLoopProxy<CustomEvents>,
+    proxy: Option<EventLoopProxy<CustomEvents>>,
 
-    noise_generator: NoiseGenerator,
-    noise_material: Res<Material>,
-    noise_uniform: NoiseUniform,
+    console_log: ConsoleLog,
+
+    /// Entities systems work on, like the scene entities and chunk meshes.
+    world: World,
+    /// What systems share besides the world, like the scene, the camera and
+    /// the ui. The engine's are in `systems`.
+    resources: Resources,
+    schedule: Schedule,
 }
-impl<'w> State<'w> {
+impl State {
     pub async fn new(
-        window: &'w Window,
+        window: Arc<Window>,
         proxy: EventLoopProxy<CustomEvents>,
+        startup: Startup,
     ) -> anyhow::Result<Self> {
+        let graphics = &startup.graphics;
         let size = window.inner_size();
 
         assert!(size.width != 0 && size.height != 0);
 
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::VULKAN,
+            backends: graphics.backend.backends(),
             ..Default::default()
         });
 
-        let surface = instance.create_surface(window)?;
-
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .context("Failed to get Adapter")?;
+        let surface = instance.create_surface(window.clone())?;
+        let adapter = request_adapter(&instance, graphics, Some(&surface)).await?;
 
-        info!("Using Device {:?}", adapter.get_info());
-
-        let (device, queue) = adapter
-            .request_device(
-                &wgpu::DeviceDescriptor {
-                    required_features: wgpu::Features::POLYGON_MODE_LINE
-                        | wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES
-                        | wgpu::Features::BGRA8UNORM_STORAGE,
-                    required_limits: wgpu::Limits::default(),
-                    label: None,
-                },
-                None,
-            )
-            .await?;
+        let (device, queue) = request_device(&adapter).await?;
 
         let surface_capabilities = surface.get_capabilities(&adapter);
         info!("SURFACE CAPABILITES: {:?}", surface_capabilities);
@@ -139,7 +122,19 @@ impl<'w> State<'w> {
             .unwrap_or(&swapchain_format);
 
         let alpha_mode = surface_capabilities.alpha_modes[0];
-        let present_mode = wgpu::PresentMode::AutoVsync;
+        let mut present_mode = graphics.present_mode.into();
+        // The auto modes always resolve to something supported.
+        if !matches!(
+            present_mode,
+            wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync
+        ) && !surface_capabilities.present_modes.contains(&present_mode)
+        {
+            warn!(
+                "Present mode {:?} is not supported, using AutoVsync. Supported modes {:?}",
+                present_mode, surface_capabilities.present_modes
+            );
+            present_mode = wgpu::PresentMode::AutoVsync;
+        }
 
         let config = wgpu::SurfaceConfiguration {
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
@@ -155,6 +150,66 @@ impl<'w> State<'w> {
         info!("WGPU CONFIG {:?}", config);
 
         surface.configure(&device, &config);
+        window.set_cursor_visible(false);
+
+        let ui_renderer =
+            UiRenderer::new(&window, &device, config.format, config.width, config.height);
+        ui_renderer.set_font_size(startup.config_file.config.ui.font_size as f32);
+        let target = RenderTarget::Window {
+            window,
+            surface,
+            config,
+        };
+
+        Self::with_target(
+            device,
+            queue,
+            target,
+            startup,
+            Some(ui_renderer),
+            Some(proxy),
+        )
+        .await
+    }
+
+    /// State rendering into an offscreen texture of `size`, without a window.
+    /// Falls back to software adapters when there is no GPU.
+    pub async fn new_headless(size: (u32, u32), startup: Startup) -> anyhow::Result<Self> {
+        let graphics = &startup.graphics;
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: graphics.backend.backends(),
+            ..Default::default()
+        });
+
+        let adapter = match request_adapter(&instance, graphics, None).await {
+            Ok(adapter) => adapter,
+            Err(err) if !graphics.force_fallback_adapter => {
+                warn!("{err:#}, trying fallback adapters");
+                let graphics = GraphicsConfig {
+                    force_fallback_adapter: true,
+                    ..graphics.clone()
+                };
+                request_adapter(&instance, &graphics, None).await?
+            }
+            Err(err) => return Err(err),
+        };
+
+        let (device, queue) = request_device(&adapter).await?;
+        let target = RenderTarget::offscreen(&device, size);
+
+        Self::with_target(device, queue, target, startup, None, None).await
+    }
+
+    async fn with_target(
+        device: Device,
+        queue: Queue,
+        target: RenderTarget,
+        startup: Startup,
+        ui_renderer: Option<UiRenderer>,
+        proxy: Option<EventLoopProxy<CustomEvents>>,
+    ) -> anyhow::Result<Self> {
+        let format = target.format();
+        let (width, height) = target.size();
         let mut engine_state = EngineState::default();
 
         let texture_bind_group_layout =
@@ -220,19 +275,29 @@ impl<'w> State<'w> {
             label: Some("Shader"),
             source: wgpu::ShaderSource::Wgsl(shader_code.into()),
         };
-        let camera = Camera::new(
+        let mut camera = Camera::new(
             Point3::new(0.0, 5.0, 10.0),
             cgmath::Deg(-90.0).into(),
             cgmath::Deg(-20.0).into(),
         );
+        let Startup {
+            graphics,
+            config_file,
+            terrain,
+            console_log,
+        } = startup;
+        let config = &config_file.config;
+        let cvars = register_cvars(config);
         let projection = Projection::new(
-            config.width,
-            config.height,
-            cgmath::Deg(45.0).into(),
+            width,
+            height,
+            cgmath::Deg(config.camera.fov).into(),
             0.1,
-            100.0,
+            // Just past the furthest generated chunk.
+            ((graphics.render_distance + 1) * CHUNK_SIZE as u32) as f32,
         );
-        let camera_controller = CameraController::new(4.0, 0.4);
+        let camera_controller =
+            CameraController::new(config.camera.speed, config.camera.sensitivity);
         let mut camera_uniform = CameraUniform::new();
         camera_uniform.update_view_proj(&camera, &projection);
 
@@ -270,7 +335,7 @@ impl<'w> State<'w> {
             .collect();
 
         let instance_data: Vec<_> = instances.iter().map(Instance::to_raw).collect();
-        info!("Instances {:?}", instance);
+        info!("Instances {}", instances.len());
         let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Instance Buffer"),
             contents: bytemuck::cast_slice(&instance_data),
@@ -336,7 +401,7 @@ impl<'w> State<'w> {
 
         let depth_texture = engine_state.create_texture(
             "depth".into(),
-            (config.width, config.height),
+            (width, height),
             TextureFormat::Depth32Float,
             &device,
         )?;
@@ -354,35 +419,65 @@ impl<'w> State<'w> {
 
         let shader = device.create_shader_module(shader);
 
-        let render_pipeline = create_render_pipeline(
+        let render_pipeline = engine_state.create_render_pipeline(
+            "default".into(),
             &device,
+            &shader,
+            "fs_main",
+            "vs_main",
+            PolygonMode::Fill,
             &render_pipeline_layout,
-            config.format,
+            format,
             Some(texture::Texture::DEPTH_FORMAT),
             &[ModelVertex::desc(), InstanceRaw::desc()],
-            &shader,
-            "Render Pipeline",
-            RenderPipeLineType::Default,
-        );
-        let wireframe_render_pipeline = create_render_pipeline(
+        )?;
+        let wireframe_render_pipeline = engine_state.create_render_pipeline(
+            "wireframe".into(),
             &device,
+            &shader,
+            "fs_main_wf",
+            "vs_main",
+            PolygonMode::Line,
             &render_pipeline_layout,
-            config.format,
+            format,
             Some(texture::Texture::DEPTH_FORMAT),
             &[ModelVertex::desc(), InstanceRaw::desc()],
-            &shader,
-            "Wireframe Render Pipeline",
-            RenderPipeLineType::Wireframe,
+        )?;
+
+        let voxel_renderer = VoxelRenderer::new(
+            &device,
+            &queue,
+            format,
+            &camera_bind_group_layout,
+            &light_bind_group_layout,
+        )
+        .await?;
+        let terrain_generator = TerrainGenerator::new(terrain);
+        info!("Terrain Seed {}", terrain_generator.config().seed);
+        let mut voxel_world = VoxelWorld::default();
+        terrain_generator.generate_area(
+            &mut voxel_world,
+            ChunkPos::new(0, 0, 0),
+            graphics.render_distance as i32,
+            -1,
+            0,
         );
+        camera.position.y = terrain_generator
+            .height_at(camera.position.x as i32, camera.position.z as i32)
+            .max(terrain_generator.config().water_level) as f32
+            + 6.0;
 
         let last_draw_call_ts = Instant::now();
 
         let delta = Duration::ZERO;
 
-        let ui_renderer =
-            UiRenderer::new(&window, &device, config.format, config.width, config.height);
-        let show_settings = false;
-        let settings = SettingsNode::default();
+        let settings = SettingsNode {
+            show_fps: config.ui.show_fps,
+            show_wireframe: config.ui.show_wireframe,
+            full_screen: config.window.fullscreen,
+            noise_save_path: config.noise.save_path.clone(),
+            ..Default::default()
+        };
 
         let plane_instance = Instance {
             position: Vector3::from_value(0.0),
@@ -392,9 +487,14 @@ impl<'w> State<'w> {
 
         let plane_renderer = PrimitiveRenderer::new::<Plane>(&device, vec![plane_instance]);
 
-        let noise_uniform = NoiseUniform::new(rand::random(), 5.0, (0.0, 0.0), (1024, 1024));
+        let mut noise_uniform = NoiseUniform::new(
+            rand::random(),
+            config.noise.frequency,
+            (0.0, 0.0),
+            config.noise.size,
+        );
+        noise_uniform.set_fractal(config.noise.fractal);
         let noise_generator = NoiseGenerator::new(&device, noise_uniform).await?;
-        window.set_cursor_visible(false);
 
         // let noise_material = Res::new(Material::new(
         //     &device,
@@ -405,7 +505,7 @@ impl<'w> State<'w> {
 
         let noise_texture = engine_state.create_texture(
             "noise".into(),
-            (1024, 1024),
+            config.noise.size,
             TextureFormat::Rgba8Unorm,
             &device,
         )?;
@@ -419,149 +519,190 @@ impl<'w> State<'w> {
         );
 
         let noise_material = Res::new(noise_material);
-        let console_node = ConsoleNode::new(proxy.clone());
-        let show_console = false;
+        let console_node = ConsoleNode::new(proxy.clone(), config_file.console_history_path());
+
+        let mut scene = Scene::default();
+        scene.register_mesh(
+            "plane".into(),
+            Res::new(Mesh::from_primitive::<Plane>(
+                &device,
+                "plane",
+                default_material.clone(),
+            )),
+        )?;
+        for mesh in obj_model.meshes.iter() {
+            scene.register_mesh(mesh.name.to_lowercase(), mesh.clone())?;
+        }
+        let device = Res::new(device);
+        let queue = Res::new(queue);
+        let mut resources = Resources::default();
+        resources.insert(device.clone());
+        resources.insert(queue.clone());
+        if let Some(window) = target.window() {
+            resources.insert(window.clone());
+        }
+        if let Some(ui_renderer) = ui_renderer {
+            resources.insert(ui_renderer);
+        }
+        resources.insert(console_log.clone());
+        resources.insert(console_node);
+        resources.insert(Panels::default());
+        resources.insert(settings);
+        resources.insert(scene);
+        resources.insert(voxel_world);
+        resources.insert(voxel_renderer);
+        resources.insert(camera);
+        resources.insert(projection);
+        resources.insert(camera_controller);
+        resources.insert(CameraBinding {
+            uniform: camera_uniform,
+            buffer: camera_buffer,
+            bind_group: camera_bind_group,
+        });
+        resources.insert(LightBinding {
+            uniform: light_uniform,
+            buffer: light_buffer,
+            bind_group: light_bind_group,
+        });
+        resources.insert(NoisePreview {
+            generator: noise_generator,
+            uniform: noise_uniform,
+            material: noise_material,
+        });
+        resources.insert(ScenePass {
+            render_pipeline,
+            wireframe_render_pipeline,
+            depth_texture,
+            plane_renderer,
+            plane_material: obj_model.materials[0].clone(),
+        });
+        resources.insert(cvars);
+        resources.insert(config_file);
 
-        let scene = Scene::default();
         Ok(Self {
-            scene,
-            console_node,
-            show_console,
-            window,
+            engine_state,
+            terrain_generator,
+            script_depth: 0,
+            target,
             queue,
             device,
-            noise_uniform,
-            noise_material,
-            noise_generator,
+            console_log,
+            world: World::default(),
+            resources,
+            schedule: engine_schedule(),
             proxy,
-            plane_renderer,
             default_material,
-            settings,
-            ui_renderer,
             delta,
-            wireframe_render_pipeline,
-            projection,
-            surface,
-            config,
-            size,
-            render_pipeline,
-
-            camera,
-            camera_uniform,
-            camera_buffer,
-            camera_bind_group,
-            camera_controller,
-
             instances,
             instance_buffer,
-
-            depth_texture,
-
             obj_model,
-
-            light_buffer,
-            light_uniform,
-
-            light_bind_group,
-
             last_draw_call_ts,
-
-            show_settings,
         })
     }
 
-    pub fn window(&self) -> &Window {
-        &self.window
-    }
-
     pub fn size(&self) -> winit::dpi::PhysicalSize<u32> {
-        self.size
+        let (width, height) = self.target.size();
+        winit::dpi::PhysicalSize::new(width, height)
     }
 
     pub fn resize(&mut self, new_size: winit::dpi::PhysicalSize<u32>) {
         info!("Resize Event {:?}", new_size);
         if new_size.width > 0 && new_size.height > 0 {
-            self.size = new_size;
-            self.config.width = new_size.width;
-            self.config.height = new_size.height;
-            self.projection.resize(new_size.width, new_size.height);
+            self.res_mut::<Projection>()
+                .resize(new_size.width, new_size.height);
             let depth_texture = TextureWithView::create(
                 "depth".into(),
-                (self.config.width, self.config.height),
+                (new_size.width, new_size.height),
                 TextureFormat::Depth32Float,
                 &self.device,
             );
-            self.depth_texture = Res::new(depth_texture);
-            self.ui_renderer
-                .resize(self.config.width, self.config.height);
-            self.surface.configure(&self.device, &self.config);
+            self.res_mut::<ScenePass>().depth_texture = Res::new(depth_texture);
+            if let Ok(mut ui_renderer) = self.resources.get_mut::<UiRenderer>() {
+                ui_renderer.resize(new_size.width, new_size.height);
+            }
+            self.target
+                .resize(&self.device, (new_size.width, new_size.height));
         }
     }
 
     pub fn input(&mut self, window_event: &WindowEvent) -> bool {
-        let ui_consumed = self.ui_renderer.on_event(self.window, window_event);
+        let Some(window) = self.target.window() else {
+            return false;
+        };
+        let ui_consumed = match self.resources.get_mut::<UiRenderer>() {
+            Ok(mut ui_renderer) => ui_renderer.on_event(window, window_event),
+            Err(_) => return false,
+        };
         if ui_consumed {
             return true;
         }
         match window_event {
             WindowEvent::MouseWheel { delta, .. } => {
-                self.camera_controller.process_scroll(delta);
+                self.res_mut::<CameraController>().process_scroll(delta);
                 true
             }
             WindowEvent::KeyboardInput { event, .. } => {
                 let is_pressed = event.state == ElementState::Pressed;
                 match event.physical_key {
-                    PhysicalKey::Code(key_code) => match key_code {
-                        KeyCode::Escape => {
-                            if is_pressed {
-                                self.show_settings = !self.show_settings;
-                                if self.show_settings {
-                                    self.show_cursor();
-                                } else {
-                                    self.hide_cursor();
+                    PhysicalKey::Code(key_code) => {
+                        let action = self
+                            .res::<ConfigFile>()
+                            .config
+                            .key_bindings
+                            .action(key_code);
+                        match action {
+                            Some(KeyAction::ToggleSettings) => {
+                                if is_pressed {
+                                    let mut panels = self.res_mut::<Panels>();
+                                    panels.settings = !panels.settings;
+                                    if panels.settings {
+                                        self.show_cursor();
+                                    } else {
+                                        self.hide_cursor();
+                                    }
+
+                                    if panels.console {
+                                        panels.console = false;
+                                        self.res_mut::<ConsoleNode>().clear();
+                                    }
                                 }
 
-                                if self.show_console {
-                                    self.show_console = false;
-                                    self.console_node.clear();
-                                }
+                                true
                             }
-
-                            true
-                        }
-                        KeyCode::KeyR => {
-                            let rt = tokio::runtime::Runtime::new().unwrap();
-                            let result =
-                                rt.block_on(NoiseGenerator::new(&self.device, self.noise_uniform));
-
-                            match result {
-                                Ok(new) => {
-                                    self.noise_generator = new;
-
-                                    info!("Reloaded Noise Generator")
-                                }
-                                Err(err) => error!("{err}"),
-                            };
-
-                            true
-                        }
-                        KeyCode::Backquote => {
-                            if is_pressed {
-                                if !self.show_console {
-                                    self.show_console = true;
-                                    self.console_node.should_request_focus();
+                            Some(KeyAction::ReloadNoise) => {
+                                let rt = tokio::runtime::Runtime::new().unwrap();
+                                let uniform = self.res::<NoisePreview>().uniform;
+                                let result =
+                                    rt.block_on(NoiseGenerator::new(&self.device, uniform));
+
+                                match result {
+                                    Ok(new) => {
+                                        self.res_mut::<NoisePreview>().generator = new;
+
+                                        info!("Reloaded Noise Generator")
+                                    }
+                                    Err(err) => error!("{err}"),
+                                };
+
+                                true
+                            }
+                            Some(KeyAction::OpenConsole) => {
+                                if is_pressed {
+                                    let mut panels = self.res_mut::<Panels>();
+                                    if !panels.console {
+                                        panels.console = true;
+                                        self.refresh_console_completions();
+                                        self.res_mut::<ConsoleNode>().should_request_focus();
+                                    }
                                 }
+                                true
                             }
-                            true
+                            Some(action) => self
+                                .res_mut::<CameraController>()
+                                .process_action(action, is_pressed),
+                            None => false,
                         }
-                        _ => {
-                            let camera_consumed = self
-                                .camera_controller
-                                .process_keyboard(key_code, is_pressed);
-
-                            camera_consumed || ui_consumed
-                        }
-                    },
+                    }
                     PhysicalKey::Unidentified(key_code) => {
                         warn!("Unidentified KeyCode {:?}", key_code);
                         false
@@ -575,203 +716,476 @@ impl<'w> State<'w> {
 
     pub fn show_cursor(&self) {
         let size = self.size();
-        self.window
-            .set_cursor_position(PhysicalPosition::new(size.width / 2, size.height / 2))
-            .unwrap();
-        self.window.set_cursor_visible(true);
+        if let Some(window) = self.target.window() {
+            window
+                .set_cursor_position(PhysicalPosition::new(size.width / 2, size.height / 2))
+                .unwrap();
+            window.set_cursor_visible(true);
+        }
     }
 
     pub fn hide_cursor(&self) {
-        self.window.set_cursor_visible(false);
+        if let Some(window) = self.target.window() {
+            window.set_cursor_visible(false);
+        }
     }
 
     pub fn update(&mut self, dt: Duration) {
         self.delta = dt;
-        if !self.show_settings {
-            self.camera_controller.update_camera(&mut self.camera, dt);
-            let old_uniform = [self.camera_uniform];
-            let old_slice: &[u8] = bytemuck::cast_slice(&old_uniform);
-            self.camera_uniform
-                .update_view_proj(&self.camera, &self.projection);
-            let new_uniform = [self.camera_uniform];
-            let new_slice: &[u8] = bytemuck::cast_slice(&new_uniform);
-            if old_slice != new_slice {
-                self.queue.write_buffer(&self.camera_buffer, 0, new_slice);
-            }
-        } else {
-            if self.settings.show_noise {
-                self.noise_generator
-                    .update_uniform(&self.device, &self.queue, self.noise_uniform);
-            }
-        }
+        self.run_stage(Stage::Input);
+        self.run_stage(Stage::Update);
+        self.run_stage(Stage::PreRender);
+    }
 
-        if self.settings.full_screen {
-            if self.window.fullscreen().is_none() {
-                self.window
-                    .set_fullscreen(Some(winit::window::Fullscreen::Borderless(None)));
-            }
-        } else {
-            if self.window.fullscreen().is_some() {
-                self.window.set_fullscreen(None);
-            }
-        }
+    fn run_stage(&mut self, stage: Stage) {
+        self.schedule
+            .run(stage, &mut self.world, &self.resources, self.delta);
+    }
+
+    fn res<T: 'static>(&self) -> Ref<'_, T> {
+        engine_res(&self.resources)
+    }
+
+    fn res_mut<T: 'static>(&self) -> RefMut<'_, T> {
+        engine_res_mut(&self.resources)
+    }
+
+    /// Changes a saved setting, the config file is written at the end of the frame.
+    fn edit_config(&self, edit: impl FnOnce(&mut AppConfig)) {
+        self.res_mut::<ConfigFile>().edit(edit);
     }
 
+    /// Runs the render stage into the next frame, then submits and presents it.
     pub fn render(&mut self) -> Result<(), wgpu::SurfaceError> {
-        if self.window.is_minimized().unwrap_or(false) {
+        let minimized = self
+            .target
+            .window()
+            .and_then(|window| window.is_minimized())
+            .unwrap_or(false);
+        if minimized {
             return Ok(());
         }
-        let output = self.surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
-
-        let mut encoder = self
+        let frame = self.target.current_frame()?;
+        let encoder = self
             .device
             .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                 label: Some("Render Encoder"),
             });
 
-        {
-            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
-                label: Some("Render Pass"),
-                color_attachments: &[Some(wgpu::RenderPassColorAttachment {
-                    view: &view,
-                    resolve_target: None,
-                    ops: wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(wgpu::Color {
-                            r: 0.1,
-                            g: 0.2,
-                            b: 0.3,
-                            a: 1.0,
-                        }),
-                        store: wgpu::StoreOp::Store,
-                    },
-                })],
-                depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
-                    view: &self.depth_texture.view,
-                    depth_ops: Some(wgpu::Operations {
-                        load: wgpu::LoadOp::Clear(1.0),
-                        store: wgpu::StoreOp::Store,
-                    }),
-                    stencil_ops: None,
-                }),
-                occlusion_query_set: None,
-                timestamp_writes: None,
-            });
-            let mut material = &self.obj_model.materials[0];
+        // Render systems draw through these, they're only there during the stage.
+        self.resources.insert(frame);
+        self.resources.insert(encoder);
+        self.run_stage(Stage::Render);
+        let encoder = self.resources.remove::<wgpu::CommandEncoder>().unwrap();
+        let frame = self.resources.remove::<Frame>().unwrap();
 
-            if self.settings.show_noise {
-                material = &self.noise_material;
-            }
+        self.queue.submit(std::iter::once(encoder.finish()));
+        frame.present();
+        self.run_stage(Stage::PostRender);
 
-            if self.settings.show_wireframe {
-                render_pass.set_pipeline(&self.wireframe_render_pipeline);
-            } else {
-                render_pass.set_pipeline(&self.render_pipeline);
-            }
+        Ok(())
+    }
 
-            self.plane_renderer.draw_with_material(
-                &material,
-                &mut render_pass,
-                &self.camera_bind_group,
-                &self.light_bind_group,
-            );
+    /// Writes the last rendered frame to a PNG, headless states only.
+    pub fn save_frame(&self, path: &Path) -> anyhow::Result<()> {
+        self.target.save_frame(&self.device, &self.queue, path)
+    }
+
+    pub fn has_pending_chunk_meshes(&self) -> bool {
+        self.res::<VoxelWorld>().has_dirty()
+    }
+
+    pub fn console_node(&self) -> RefMut<'_, ConsoleNode> {
+        self.res_mut()
+    }
+
+    pub fn camera_controller(&self) -> RefMut<'_, CameraController> {
+        self.res_mut()
+    }
+
+    /// Names the console completes arguments with.
+    pub fn refresh_console_completions(&self) {
+        let scene = self.res::<Scene>();
+        let cvars = self.res::<CvarRegistry>();
+        let names = self
+            .world
+            .query::<&Name>()
+            .map(|(_, name)| name.0.as_str())
+            .chain(scene.mesh_names())
+            .chain(self.engine_state.texture_names())
+            .chain(self.engine_state.render_pipeline_names())
+            .chain(cvars.iter().map(Cvar::name))
+            .map(String::from)
+            .collect();
+        self.console_node().set_completions(names);
+    }
+
+    pub fn console_log(&self) -> &ConsoleLog {
+        &self.console_log
+    }
+
+    pub fn cvars(&self) -> Ref<'_, CvarRegistry> {
+        self.res()
+    }
+
+    /// Sets the cvar `name` from its text form and runs its change callbacks.
+    pub fn set_cvar(&mut self, name: &str, value: &str) -> Result<CvarValue, CvarError> {
+        let (callbacks, value) = {
+            let mut cvars = self.res_mut::<CvarRegistry>();
+            let callbacks = cvars.set(name, value)?;
+            (callbacks, cvars.get(name)?.value().clone())
+        };
+        for on_change in callbacks {
+            on_change(self, &value);
         }
 
-        let mut should_read_noise_output = false;
-        {
-            self.ui_renderer.draw(
-                &self.device,
-                &self.queue,
-                &self.window,
-                &mut encoder,
-                &view,
-                |ui| {
-                    if self.show_settings {
-                        self.settings.add_ui(ui);
-                        // self.noise_uniform.add_ui(ui);
-                    }
-                    if self.settings.show_fps {
-                        DebugOverlay { dt: self.delta }.add_ui(ui);
-                    }
+        Ok(value)
+    }
 
-                    if self.show_console {
-                        self.console_node.add_ui(ui);
-                    }
-                },
-            );
+    pub fn set_render_pipeline(&mut self, pipeline_name: &str) -> Result<(), EngineError> {
+        let render_pipeline = self
+            .engine_state
+            .get_render_pipeline(pipeline_name)
+            .ok_or_else(|| EngineError::NameNotFound(pipeline_name.into()))?;
+        self.res_mut::<ScenePass>().render_pipeline = render_pipeline;
 
-            if self.settings.save_noise_texture {
-                self.settings.save_noise_texture = false;
+        Ok(())
+    }
 
-                self.noise_generator.compute(&mut encoder);
-                self.noise_generator
-                    .copy_to_texture(&mut encoder, &self.noise_material.diffuse_texture.texture);
+    pub fn noise_uniform(&self) -> NoiseUniform {
+        self.res::<NoisePreview>().uniform
+    }
 
-                should_read_noise_output = true;
-            }
+    /// Computes the noise into `texture_name`, creating the texture if needed,
+    /// and writes it to `save_path` in the background when given.
+    pub fn generate_noise(
+        &mut self,
+        noise_uniform: NoiseUniform,
+        texture_name: &str,
+        save_path: Option<PathBuf>,
+    ) -> Result<(), EngineError> {
+        let size = noise_uniform.size();
+        let texture = match self.engine_state.get_texture(texture_name) {
+            Some(texture) => texture,
+            None => self.engine_state.create_texture(
+                texture_name.into(),
+                size,
+                TextureFormat::Rgba8Unorm,
+                &self.device,
+            )?,
+        };
+
+        let texture_size = texture.texture.size();
+        let found = (texture_size.width, texture_size.height);
+        if found != size {
+            return Err(EngineError::SizeMismatch {
+                expected: size,
+                found,
+            });
+        }
+        if texture.texture.format() != TextureFormat::Rgba8Unorm {
+            return Err(EngineError::IncompatibleFormat(texture.texture.format()));
         }
 
-        let _idx = self.queue.submit(std::iter::once(encoder.finish()));
-        output.present();
+        let mut noise = self.res_mut::<NoisePreview>();
+        noise.uniform = noise_uniform;
+        noise
+            .generator
+            .update_uniform(&self.device, &self.queue, noise_uniform);
 
-        if should_read_noise_output {
-            // self.noise_generator.async_read();
+        let mut encoder = self
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Noise Encoder"),
+            });
+        noise.generator.compute(&mut encoder);
+        noise
+            .generator
+            .copy_to_texture(&mut encoder, &texture.texture);
+        if let Some(path) = save_path {
+            noise
+                .generator
+                .save_to_file(&self.device, &mut encoder, path);
         }
+        self.queue.submit(std::iter::once(encoder.finish()));
+        noise.generator.map_pending_reads();
+
+        Ok(())
+    }
+
+    pub fn spawn(&mut self, name: String, mesh_name: &str) -> Result<(), EngineError> {
+        let transform = Instance {
+            position: Vector3::from_value(0.0),
+            rotation: Quaternion::from_axis_angle(Vector3::unit_y(), Deg(0.0)),
+            scale: Vector3::from_value(1.0),
+        };
+        engine_res::<Scene>(&self.resources).spawn(&mut self.world, name, mesh_name, transform)?;
 
-        // let maintain = wgpu::MaintainBase::WaitForSubmissionIndex(idx);
-        // loop {
-        //     let result = self.render_target.device.poll(maintain.clone());
-        //     match result {
-        //         MaintainResult::SubmissionQueueEmpty => break,
-        //         MaintainResult::Ok => continue,
-        //     }
-        // }
+        Ok(())
+    }
 
-        // if should_read_noise_output {
-        //     self.noise_generator.read_and_save_to_img();
-        // }
+    pub fn update_entity(
+        &mut self,
+        name: &str,
+        f: impl FnOnce(&mut Instance),
+    ) -> Result<(), EngineError> {
+        let transform = scene::find_entity(&self.world, name)
+            .and_then(|entity| self.world.get_component_mut::<Instance>(entity))
+            .ok_or_else(|| EngineError::NameNotFound(name.into()))?;
+        f(transform);
 
         Ok(())
     }
-    pub fn spawn(&mut self, mesh: MeshType) {
-        match mesh {
-            MeshType::Plane => {
-                let plane_instance = Instance {
-                    position: Vector3::from_value(0.0),
-                    rotation: Quaternion::from_axis_angle(Vector3::unit_y(), Deg(0.0)),
-                    scale: Vector3::from_value(1.0),
-                };
+}
+
+/// Cvars of the settings ui and camera, starting at their config values.
+/// One of the engine's resources. `with_target` adds them all and systems
+/// only borrow them while they run, so outside of systems they're always there.
+fn engine_res<T: 'static>(resources: &Resources) -> Ref<'_, T> {
+    resources.get().unwrap_or_else(|err| panic!("{err}"))
+}
 
-                self.plane_renderer
-                    .add_instance(plane_instance, &self.queue);
+fn engine_res_mut<T: 'static>(resources: &Resources) -> RefMut<'_, T> {
+    resources.get_mut().unwrap_or_else(|err| panic!("{err}"))
+}
+
+fn register_cvars(config: &AppConfig) -> CvarRegistry {
+    let mut cvars = CvarRegistry::default();
+    cvars.register(
+        Cvar::new(
+            "ui.show_fps",
+            "Show the frame time overlay",
+            config.ui.show_fps,
+        )
+        .on_change(|state, value| {
+            if let CvarValue::Bool(value) = value {
+                state.res_mut::<SettingsNode>().show_fps = *value;
+                state.edit_config(|config| config.ui.show_fps = *value);
             }
-        }
-    }
+        }),
+    );
+    cvars.register(
+        Cvar::new(
+            "render.wireframe",
+            "Draw triangle edges only",
+            config.ui.show_wireframe,
+        )
+        .on_change(|state, value| {
+            if let CvarValue::Bool(value) = value {
+                state.res_mut::<SettingsNode>().show_wireframe = *value;
+                state.edit_config(|config| config.ui.show_wireframe = *value);
+            }
+        }),
+    );
+    cvars.register(
+        Cvar::new(
+            "render.show_noise",
+            "Texture the plane with the noise",
+            false,
+        )
+        .on_change(|state, value| {
+            if let CvarValue::Bool(value) = value {
+                state.res_mut::<SettingsNode>().show_noise = *value;
+            }
+        }),
+    );
+    cvars.register(
+        Cvar::new(
+            "render.light_color",
+            "Color of the light, 0 to 1 per channel",
+            Vector3::new(1.0, 1.0, 1.0),
+        )
+        .range(0.0, 1.0)
+        .on_change(|state, value| {
+            if let CvarValue::Vec3(color) = value {
+                let mut light = state.res_mut::<LightBinding>();
+                light.uniform.color = (*color).into();
+                state
+                    .queue
+                    .write_buffer(&light.buffer, 0, bytemuck::cast_slice(&[light.uniform]));
+            }
+        }),
+    );
+    cvars.register(
+        Cvar::new(
+            "window.fullscreen",
+            "Borderless fullscreen",
+            config.window.fullscreen,
+        )
+        .on_change(|state, value| {
+            if let CvarValue::Bool(value) = value {
+                state.res_mut::<SettingsNode>().full_screen = *value;
+                state.edit_config(|config| config.window.fullscreen = *value);
+            }
+        }),
+    );
+    cvars.register(
+        Cvar::new(
+            "camera.fov",
+            "Vertical field of view in degrees",
+            config.camera.fov,
+        )
+        .range(1.0, 179.0)
+        .on_change(|state, value| {
+            if let CvarValue::Float(fov) = value {
+                state.res_mut::<Projection>().set_fovy(Deg(*fov).into());
+                state.edit_config(|config| config.camera.fov = *fov);
+            }
+        }),
+    );
+    cvars.register(
+        Cvar::new("camera.speed", "Blocks per second", config.camera.speed)
+            .range(0.0, f64::INFINITY)
+            .on_change(|state, value| {
+                if let CvarValue::Float(speed) = value {
+                    state.camera_controller().set_speed(*speed);
+                    state.edit_config(|config| config.camera.speed = *speed);
+                }
+            }),
+    );
+    cvars.register(
+        Cvar::new(
+            "camera.sensitivity",
+            "Mouse look and scroll sensitivity",
+            config.camera.sensitivity,
+        )
+        .range(0.0, f64::INFINITY)
+        .on_change(|state, value| {
+            if let CvarValue::Float(sensitivity) = value {
+                state.camera_controller().set_sensitivity(*sensitivity);
+                state.edit_config(|config| config.camera.sensitivity = *sensitivity);
+            }
+        }),
+    );
+    cvars.register(
+        Cvar::new(
+            "noise.frequency",
+            "Base frequency of the noise texture",
+            config.noise.frequency,
+        )
+        .range(0.0, f64::INFINITY)
+        .on_change(|state, value| {
+            if let CvarValue::Float(frequency) = value {
+                state
+                    .res_mut::<NoisePreview>()
+                    .uniform
+                    .set_frequency(*frequency);
+                state.edit_config(|config| config.noise.frequency = *frequency);
+            }
+        }),
+    );
+    cvars.register(
+        Cvar::new(
+            "noise.octaves",
+            "Octaves of the noise texture",
+            config.noise.fractal.octaves as i64,
+        )
+        .range(1.0, MAX_OCTAVES as f64)
+        .on_change(|state, value| {
+            if let CvarValue::Int(octaves) = value {
+                let mut noise = state.res_mut::<NoisePreview>();
+                let mut fractal = noise.uniform.fractal();
+                fractal.octaves = *octaves as u32;
+                noise.uniform.set_fractal(fractal);
+                state.edit_config(|config| config.noise.fractal.octaves = fractal.octaves);
+            }
+        }),
+    );
+    cvars.register(
+        Cvar::new(
+            "noise.save_path",
+            "Where the settings ui saves the noise texture",
+            config.noise.save_path.clone(),
+        )
+        .on_change(|state, value| {
+            if let CvarValue::String(path) = value {
+                state.res_mut::<SettingsNode>().noise_save_path = path.clone();
+                state.edit_config(|config| config.noise.save_path = path.clone());
+            }
+        }),
+    );
+
+    cvars
 }
 
-pub fn save_tmp_image(size: (u32, u32), data: &[u8]) {
-    info!("Saving Image /tmp/noise.png");
-    image::save_buffer_with_format(
-        "/tmp/noise.png",
-        data,
-        size.0,
-        size.1,
-        image::ExtendedColorType::Rgba8,
-        image::ImageFormat::Png,
+/// Adapter matching the graphics config, the error lists the available
+/// adapters when none do.
+async fn request_adapter(
+    instance: &wgpu::Instance,
+    graphics: &GraphicsConfig,
+    compatible_surface: Option<&wgpu::Surface<'_>>,
+) -> anyhow::Result<wgpu::Adapter> {
+    let adapter = instance
+        .request_adapter(&wgpu::RequestAdapterOptions {
+            power_preference: graphics.power_preference.into(),
+            compatible_surface,
+            force_fallback_adapter: graphics.force_fallback_adapter,
+        })
+        .await;
+
+    if let Some(adapter) = adapter {
+        info!("Using Device {:?}", adapter.get_info());
+        return Ok(adapter);
+    }
+
+    // List adapters of every backend, the configured one might have none.
+    let all_backends = wgpu::Instance::new(wgpu::InstanceDescriptor::default());
+    let adapters: Vec<_> = all_backends
+        .enumerate_adapters(wgpu::Backends::all())
+        .iter()
+        .map(|adapter| {
+            let info = adapter.get_info();
+            format!(
+                "\n  {} ({:?}, {:?})",
+                info.name, info.backend, info.device_type
+            )
+        })
+        .collect();
+    let adapters = if adapters.is_empty() {
+        " none".to_string()
+    } else {
+        adapters.concat()
+    };
+
+    anyhow::bail!(
+        "No adapter for backend {:?}, power preference {:?}, fallback adapter {}. Available adapters:{}",
+        graphics.backend,
+        graphics.power_preference,
+        graphics.force_fallback_adapter,
+        adapters
     )
-    .unwrap();
 }
 
-pub enum MeshType {
-    Plane,
+/// Wireframes and bgra storage are nice to have, software adapters often
+/// lack them.
+async fn request_device(adapter: &wgpu::Adapter) -> anyhow::Result<(Device, Queue)> {
+    let required_features = wgpu::Features::TEXTURE_ADAPTER_SPECIFIC_FORMAT_FEATURES;
+    let optional_features = wgpu::Features::POLYGON_MODE_LINE | wgpu::Features::BGRA8UNORM_STORAGE;
+    let missing_features = optional_features - adapter.features();
+    if !missing_features.is_empty() {
+        warn!(
+            "Adapter is missing optional features {:?}",
+            missing_features
+        );
+    }
+
+    let (device, queue) = adapter
+        .request_device(
+            &wgpu::DeviceDescriptor {
+                required_features: required_features | (optional_features & adapter.features()),
+                required_limits: wgpu::Limits::default(),
+                label: None,
+            },
+            None,
+        )
+        .await?;
+
+    Ok((device, queue))
 }
 
 pub enum RenderPipeLineType {
     Default,
     Wireframe,
-    Noise,
 }
 
 pub fn create_render_pipeline(
@@ -787,7 +1201,6 @@ pub fn create_render_pipeline(
     let fragment_entry_point = match ty {
         RenderPipeLineType::Default => "fs_main",
         RenderPipeLineType::Wireframe => "fs_main_wf",
-        RenderPipeLineType::Noise => "fs_main_noise",
     };
     let polygon_mode = if matches!(ty, RenderPipeLineType::Wireframe) {
         PolygonMode::Line
@@ -812,7 +1225,7 @@ pub fn create_render_pipeline(
             strip_index_format: None,
             front_face: wgpu::FrontFace::Ccw,
             cull_mode: Some(wgpu::Face::Back),
-            polygon_mode,
+            polygon_mode: supported_polygon_mode(device, polygon_mode),
             unclipped_depth: false,
             conservative: false,
         },
@@ -0,0 +1,434 @@
+use std::{sync::Arc, time::Duration};
+
+use wgpu::{CommandEncoder, Device, Queue, RenderPipeline};
+use winit::window::Window;
+
+use crate::{
+    app_config::ConfigFile,
+    camera::{Camera, CameraController, CameraUniform, Frustum, Projection},
+    console_log::ConsoleLog,
+    cvars::CvarRegistry,
+    ecs::{
+        ecs::Res,
+        resources::{ResourceError, Resources},
+        world::World,
+    },
+    engine_state::TextureWithView,
+    noise::{NoiseGenerator, NoiseUniform},
+    render_target::Frame,
+    scene::Scene,
+    schedule::{Schedule, Stage, System},
+    ui::{
+        console::ConsoleNode,
+        renderer::{UiNode, UiRenderer},
+        settings::SettingsNode,
+        text::DebugOverlay,
+    },
+    voxel::{
+        light::LightUniform, mesher::mesh_chunk, model::Material,
+        renderer::Renderer as VoxelRenderer, vertex::PrimitiveRenderer, world::VoxelWorld,
+    },
+};
+
+const CHUNK_MESHES_PER_FRAME: usize = 4;
+
+/// Which of the ui panels are open.
+#[derive(Debug, Default)]
+pub struct Panels {
+    pub settings: bool,
+    pub console: bool,
+}
+
+pub struct CameraBinding {
+    pub uniform: CameraUniform,
+    pub buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+}
+
+pub struct LightBinding {
+    pub uniform: LightUniform,
+    pub buffer: wgpu::Buffer,
+    pub bind_group: wgpu::BindGroup,
+}
+
+/// The noise texture of the settings ui.
+pub struct NoisePreview {
+    pub generator: NoiseGenerator,
+    pub uniform: NoiseUniform,
+    pub material: Res<Material>,
+}
+
+/// What the scene pass draws with, besides the scene itself.
+pub struct ScenePass {
+    pub render_pipeline: Res<RenderPipeline>,
+    pub wireframe_render_pipeline: Res<RenderPipeline>,
+    pub depth_texture: Res<TextureWithView>,
+    pub plane_renderer: PrimitiveRenderer,
+    pub plane_material: Res<Material>,
+}
+
+/// Systems the engine itself runs every frame.
+pub fn engine_schedule() -> Schedule {
+    let mut schedule = Schedule::default();
+    let systems = [
+        (Stage::Input, System::new("console_log", console_log_system)),
+        (Stage::Update, System::new("chunk_mesh", chunk_mesh_system)),
+        (Stage::Update, System::new("camera", camera_system)),
+        (
+            Stage::Update,
+            System::new("noise_preview", noise_preview_system),
+        ),
+        (Stage::Update, System::new("fullscreen", fullscreen_system)),
+        (
+            Stage::PreRender,
+            System::new("instance_upload", instance_upload_system),
+        ),
+        (
+            Stage::PreRender,
+            System::new("noise_reads", noise_reads_system),
+        ),
+        (
+            Stage::Render,
+            System::new("scene", scene_system).before("ui"),
+        ),
+        (Stage::Render, System::new("ui", ui_system)),
+        // Saves what the settings ui asked for this frame.
+        (
+            Stage::Render,
+            System::new("noise_save", noise_save_system).after("ui"),
+        ),
+        (
+            Stage::PostRender,
+            System::new("noise_map_reads", noise_map_reads_system),
+        ),
+        (
+            Stage::PostRender,
+            System::new("ui_cvars", ui_cvars_system).before("save_settings"),
+        ),
+        (
+            Stage::PostRender,
+            System::new("save_settings", save_settings_system),
+        ),
+    ];
+    for (stage, system) in systems {
+        schedule.add(stage, system).unwrap();
+    }
+
+    schedule
+}
+
+fn console_log_system(
+    _: &mut World,
+    resources: &Resources,
+    _dt: Duration,
+) -> Result<(), ResourceError> {
+    let mut console_node = resources.get_mut::<ConsoleNode>()?;
+    for line in resources.get::<ConsoleLog>()?.take_lines() {
+        console_node.add_log(&line);
+    }
+
+    Ok(())
+}
+
+/// Meshes a few of the chunks that changed.
+fn chunk_mesh_system(
+    world: &mut World,
+    resources: &Resources,
+    _dt: Duration,
+) -> Result<(), ResourceError> {
+    let mut voxel_world = resources.get_mut::<VoxelWorld>()?;
+    let mut voxel_renderer = resources.get_mut::<VoxelRenderer>()?;
+    let device = resources.get::<Res<Device>>()?;
+    for pos in voxel_world.take_dirty(CHUNK_MESHES_PER_FRAME) {
+        match mesh_chunk(&voxel_world, pos) {
+            Some(mesh) => voxel_renderer.upload_chunk(world, &device, pos, &mesh),
+            None => voxel_renderer.free_chunk(world, pos),
+        }
+    }
+
+    Ok(())
+}
+
+/// Moves the camera, unless the settings ui has the mouse.
+fn camera_system(_: &mut World, resources: &Resources, dt: Duration) -> Result<(), ResourceError> {
+    if resources.get::<Panels>()?.settings {
+        return Ok(());
+    }
+    let mut camera = resources.get_mut::<Camera>()?;
+    resources
+        .get_mut::<CameraController>()?
+        .update_camera(&mut camera, dt);
+    let mut binding = resources.get_mut::<CameraBinding>()?;
+    let projection = resources.get::<Projection>()?;
+    let old_uniform = [binding.uniform];
+    let old_slice: &[u8] = bytemuck::cast_slice(&old_uniform);
+    binding.uniform.update_view_proj(&camera, &projection);
+    let new_uniform = [binding.uniform];
+    let new_slice: &[u8] = bytemuck::cast_slice(&new_uniform);
+    if old_slice != new_slice {
+        resources
+            .get::<Res<Queue>>()?
+            .write_buffer(&binding.buffer, 0, new_slice);
+    }
+
+    Ok(())
+}
+
+/// Keeps the noise preview in sync with the settings ui.
+fn noise_preview_system(
+    _: &mut World,
+    resources: &Resources,
+    _dt: Duration,
+) -> Result<(), ResourceError> {
+    if resources.get::<Panels>()?.settings && resources.get::<SettingsNode>()?.show_noise {
+        let mut noise = resources.get_mut::<NoisePreview>()?;
+        let device = resources.get::<Res<Device>>()?;
+        let queue = resources.get::<Res<Queue>>()?;
+        let uniform = noise.uniform;
+        noise.generator.update_uniform(&device, &queue, uniform);
+    }
+
+    Ok(())
+}
+
+fn fullscreen_system(
+    _: &mut World,
+    resources: &Resources,
+    _dt: Duration,
+) -> Result<(), ResourceError> {
+    if !resources.contains::<Arc<Window>>() {
+        return Ok(());
+    }
+    let window = resources.get::<Arc<Window>>()?;
+    if resources.get::<SettingsNode>()?.full_screen {
+        if window.fullscreen().is_none() {
+            window.set_fullscreen(Some(winit::window::Fullscreen::Borderless(None)));
+        }
+    } else if window.fullscreen().is_some() {
+        window.set_fullscreen(None);
+    }
+
+    Ok(())
+}
+
+fn instance_upload_system(
+    world: &mut World,
+    resources: &Resources,
+    _dt: Duration,
+) -> Result<(), ResourceError> {
+    let device = resources.get::<Res<Device>>()?;
+    let queue = resources.get::<Res<Queue>>()?;
+    resources
+        .get_mut::<Scene>()?
+        .update_instances(world, &device, &queue);
+
+    Ok(())
+}
+
+fn noise_reads_system(
+    _: &mut World,
+    resources: &Resources,
+    _dt: Duration,
+) -> Result<(), ResourceError> {
+    let device = resources.get::<Res<Device>>()?;
+    resources
+        .get_mut::<NoisePreview>()?
+        .generator
+        .poll_pending_reads(&device);
+
+    Ok(())
+}
+
+/// Clears the frame and draws the plane, the scene entities and the chunks.
+fn scene_system(
+    world: &mut World,
+    resources: &Resources,
+    _dt: Duration,
+) -> Result<(), ResourceError> {
+    let frame = resources.get::<Frame>()?;
+    let mut encoder = resources.get_mut::<CommandEncoder>()?;
+    let pass = resources.get::<ScenePass>()?;
+    let settings = resources.get::<SettingsNode>()?;
+    let noise = resources.get::<NoisePreview>()?;
+    let camera = resources.get::<CameraBinding>()?;
+    let light = resources.get::<LightBinding>()?;
+    let scene = resources.get::<Scene>()?;
+    let voxel_renderer = resources.get::<VoxelRenderer>()?;
+    let frustum = Frustum::from_camera(
+        &*resources.get::<Camera>()?,
+        &*resources.get::<Projection>()?,
+    );
+
+    let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: Some("Render Pass"),
+        color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+            view: &frame.view,
+            resolve_target: None,
+            ops: wgpu::Operations {
+                load: wgpu::LoadOp::Clear(wgpu::Color {
+                    r: 0.1,
+                    g: 0.2,
+                    b: 0.3,
+                    a: 1.0,
+                }),
+                store: wgpu::StoreOp::Store,
+            },
+        })],
+        depth_stencil_attachment: Some(wgpu::RenderPassDepthStencilAttachment {
+            view: &pass.depth_texture.view,
+            depth_ops: Some(wgpu::Operations {
+                load: wgpu::LoadOp::Clear(1.0),
+                store: wgpu::StoreOp::Store,
+            }),
+            stencil_ops: None,
+        }),
+        occlusion_query_set: None,
+        timestamp_writes: None,
+    });
+    let material = if settings.show_noise {
+        &noise.material
+    } else {
+        &pass.plane_material
+    };
+
+    if settings.show_wireframe {
+        render_pass.set_pipeline(&pass.wireframe_render_pipeline);
+    } else {
+        render_pass.set_pipeline(&pass.render_pipeline);
+    }
+
+    pass.plane_renderer.draw_with_material(
+        material,
+        &mut render_pass,
+        &camera.bind_group,
+        &light.bind_group,
+    );
+    scene.draw(&mut render_pass, &camera.bind_group, &light.bind_group);
+    voxel_renderer.render(
+        &mut render_pass,
+        world,
+        &frustum,
+        settings.show_wireframe,
+        &camera.bind_group,
+        &light.bind_group,
+    );
+
+    Ok(())
+}
+
+/// Draws the open panels over the scene, windowed states only.
+fn ui_system(_: &mut World, resources: &Resources, dt: Duration) -> Result<(), ResourceError> {
+    if !resources.contains::<UiRenderer>() {
+        return Ok(());
+    }
+    let window = resources.get::<Arc<Window>>()?;
+    let mut ui_renderer = resources.get_mut::<UiRenderer>()?;
+    let frame = resources.get::<Frame>()?;
+    let mut encoder = resources.get_mut::<CommandEncoder>()?;
+    let panels = resources.get::<Panels>()?;
+    let mut settings = resources.get_mut::<SettingsNode>()?;
+    let mut noise = resources.get_mut::<NoisePreview>()?;
+    let mut console_node = resources.get_mut::<ConsoleNode>()?;
+    let device = resources.get::<Res<Device>>()?;
+    let queue = resources.get::<Res<Queue>>()?;
+
+    ui_renderer.draw(&device, &queue, &window, &mut encoder, &frame.view, |ui| {
+        if panels.settings {
+            settings.add_ui(ui);
+            if settings.show_noise {
+                noise.uniform.add_ui(ui);
+            }
+        }
+        if settings.show_fps {
+            DebugOverlay { dt }.add_ui(ui);
+        }
+
+        if panels.console {
+            console_node.add_ui(ui);
+        }
+    });
+
+    Ok(())
+}
+
+fn noise_save_system(
+    _: &mut World,
+    resources: &Resources,
+    _dt: Duration,
+) -> Result<(), ResourceError> {
+    let mut settings = resources.get_mut::<SettingsNode>()?;
+    if !std::mem::take(&mut settings.save_noise_texture) {
+        return Ok(());
+    }
+
+    let mut encoder = resources.get_mut::<CommandEncoder>()?;
+    let mut noise = resources.get_mut::<NoisePreview>()?;
+    let device = resources.get::<Res<Device>>()?;
+    let noise = &mut *noise;
+    noise.generator.compute(&mut encoder);
+    noise
+        .generator
+        .copy_to_texture(&mut encoder, &noise.material.diffuse_texture.texture);
+    noise.generator.save_to_file(
+        &device,
+        &mut encoder,
+        settings.noise_save_path.clone().into(),
+    );
+
+    Ok(())
+}
+
+fn noise_map_reads_system(
+    _: &mut World,
+    resources: &Resources,
+    _dt: Duration,
+) -> Result<(), ResourceError> {
+    resources
+        .get_mut::<NoisePreview>()?
+        .generator
+        .map_pending_reads();
+
+    Ok(())
+}
+
+/// Copies values the ui edits directly back into their cvars, and into
+/// the config for the saved ones.
+fn ui_cvars_system(
+    _: &mut World,
+    resources: &Resources,
+    _dt: Duration,
+) -> Result<(), ResourceError> {
+    let mut cvars = resources.get_mut::<CvarRegistry>()?;
+    let mut settings = resources.get_mut::<SettingsNode>()?;
+    let noise_uniform = resources.get::<NoisePreview>()?.uniform;
+    cvars.store("render.show_noise", settings.show_noise);
+    cvars.store("noise.octaves", noise_uniform.fractal().octaves as i64);
+    cvars.store("noise.frequency", noise_uniform.frequency());
+    if !std::mem::take(&mut settings.changed) {
+        return Ok(());
+    }
+
+    cvars.store("ui.show_fps", settings.show_fps);
+    cvars.store("render.wireframe", settings.show_wireframe);
+    cvars.store("window.fullscreen", settings.full_screen);
+    cvars.store("noise.save_path", settings.noise_save_path.clone());
+
+    resources.get_mut::<ConfigFile>()?.edit(|config| {
+        config.ui.show_fps = settings.show_fps;
+        config.ui.show_wireframe = settings.show_wireframe;
+        config.window.fullscreen = settings.full_screen;
+        config.noise.save_path = settings.noise_save_path.clone();
+    });
+
+    Ok(())
+}
+
+fn save_settings_system(
+    _: &mut World,
+    resources: &Resources,
+    _dt: Duration,
+) -> Result<(), ResourceError> {
+    resources.get_mut::<ConfigFile>()?.save_if_changed();
+
+    Ok(())
+}
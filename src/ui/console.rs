@@ -1,57 +1,331 @@
-use egui::{Color32, RichText};
-use log::info;
+use std::{
+    collections::VecDeque,
+    io::Write,
+    path::{Path, PathBuf},
+    time::SystemTime,
+};
+
+use egui::{
+    text::{CCursor, CCursorRange, LayoutJob, TextFormat},
+    Color32, Key, Modifiers,
+};
+use log::{warn, Level};
 use winit::event_loop::EventLoopProxy;
 
-use crate::CustomEvents;
+use crate::{commands::Command, console_log::LogLine, CustomEvents};
 
 use super::renderer::UiNode;
 
-pub const HISTORY_CAPACITY: usize = 2048;
-pub const HISTORY_LENGTH: usize = 1024;
+/// Entries kept in the console, the oldest are dropped first.
+pub const HISTORY_CAPACITY: usize = 1024;
+/// Submitted commands kept for Up/Down recall.
+pub const MAX_RECALL: usize = 500;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EntryKind {
+    /// A submitted command.
+    Input,
+    /// What a command printed.
+    Output,
+    Debug,
+    Info,
+    Warning,
+    Error,
+}
+
+impl EntryKind {
+    fn color(self) -> Color32 {
+        match self {
+            EntryKind::Input => Color32::from_rgb(0, 70, 160),
+            EntryKind::Output | EntryKind::Info => Color32::BLACK,
+            EntryKind::Debug => Color32::DARK_GRAY,
+            EntryKind::Warning => Color32::from_rgb(200, 120, 0),
+            EntryKind::Error => Color32::RED,
+        }
+    }
+}
+
+impl From<Level> for EntryKind {
+    fn from(level: Level) -> Self {
+        match level {
+            Level::Error => EntryKind::Error,
+            Level::Warn => EntryKind::Warning,
+            Level::Info => EntryKind::Info,
+            Level::Debug | Level::Trace => EntryKind::Debug,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ConsoleEntry {
+    pub kind: EntryKind,
+    pub time: SystemTime,
+    pub text: String,
+}
+
+impl ConsoleEntry {
+    /// Local time of day the entry was added, like the log's timestamps.
+    fn timestamp(&self) -> String {
+        fastdate::DateTime::from(self.time)
+            .set_offset(fastdate::offset_sec())
+            .format("hh:mm:ss")
+    }
+
+    /// The entry as plain text, for copying. Warnings and errors keep their
+    /// kind since the colour is lost.
+    fn to_line(&self) -> String {
+        match self.kind {
+            EntryKind::Input => format!("{} > {}", self.timestamp(), self.text),
+            EntryKind::Warning => format!("{} warning: {}", self.timestamp(), self.text),
+            EntryKind::Error => format!("{} error: {}", self.timestamp(), self.text),
+            _ => format!("{} {}", self.timestamp(), self.text),
+        }
+    }
+}
 
 pub struct ConsoleNode {
-    history: String,
+    history: VecDeque<ConsoleEntry>,
+    /// Only entries containing this are shown, ignoring case.
+    search: String,
     command: String,
-    proxy: EventLoopProxy<CustomEvents>,
+    /// Submitted commands are sent here, headless states have no event loop.
+    proxy: Option<EventLoopProxy<CustomEvents>>,
     request_focus: bool,
+    /// Previously submitted commands, oldest first.
+    submitted: Vec<String>,
+    /// Index into `submitted` while recalling, `None` while typing a new command.
+    recall_index: Option<usize>,
+    /// The new command, kept while recalling older ones.
+    draft: String,
+    /// Where `submitted` is saved between sessions.
+    submitted_path: Option<PathBuf>,
+    subcommands: Vec<String>,
+    /// Argument names Tab completes, like entities, textures and cvars.
+    completions: Vec<String>,
 }
 
 impl ConsoleNode {
-    pub fn new(proxy: EventLoopProxy<CustomEvents>) -> Self {
+    pub fn new(
+        proxy: Option<EventLoopProxy<CustomEvents>>,
+        submitted_path: Option<PathBuf>,
+    ) -> Self {
+        let submitted = submitted_path
+            .as_ref()
+            .map(|path| load_submitted(path))
+            .unwrap_or_default();
+
         Self {
             command: String::new(),
-            history: String::with_capacity(HISTORY_CAPACITY),
+            history: VecDeque::with_capacity(HISTORY_CAPACITY),
+            search: String::new(),
             proxy,
             request_focus: false,
+            submitted,
+            recall_index: None,
+            draft: String::new(),
+            submitted_path,
+            subcommands: Command::subcommand_names(),
+            completions: Vec::new(),
         }
     }
 
-    pub fn add_to_history(&mut self, s: &str) {
-        if self.history.len() + s.len() > HISTORY_CAPACITY {
-            let mut new_history = String::with_capacity(HISTORY_CAPACITY);
-            if let Some(index) = self.history[self.history.len() - HISTORY_LENGTH..].find("\n") {
-                new_history += &self.history[self.history.len() - HISTORY_LENGTH + index + 1..];
-            }
-            self.history = new_history;
+    pub fn add_to_history(&mut self, kind: EntryKind, text: &str) {
+        if self.history.len() == HISTORY_CAPACITY {
+            self.history.pop_front();
         }
-
-        self.history += s;
-        self.history += "\n";
+        self.history.push_back(ConsoleEntry {
+            kind,
+            time: SystemTime::now(),
+            text: text.trim_end().into(),
+        });
     }
 
     pub fn clear(&mut self) {
         self.command.clear();
+        self.recall_index = None;
     }
 
     pub fn should_request_focus(&mut self) {
         self.request_focus = true;
     }
+
+    pub fn add_log(&mut self, line: &LogLine) {
+        self.add_to_history(
+            line.level.into(),
+            &format!("{}: {}", line.module, line.message),
+        );
+    }
+
+    pub fn set_completions(&mut self, completions: Vec<String>) {
+        self.completions = completions;
+    }
+
+    fn submit(&mut self) {
+        let cmd = std::mem::take(&mut self.command);
+        self.recall_index = None;
+        self.add_to_history(EntryKind::Input, &cmd);
+        self.remember(&cmd);
+        if let Some(proxy) = self.proxy.as_ref() {
+            proxy.send_event(CustomEvents::UserCommand(cmd)).unwrap();
+        }
+    }
+
+    fn remember(&mut self, cmd: &str) {
+        let cmd = cmd.trim();
+        if cmd.is_empty() || self.submitted.last().is_some_and(|last| last == cmd) {
+            return;
+        }
+        if self.submitted.len() == MAX_RECALL {
+            self.submitted.remove(0);
+        }
+        self.submitted.push(cmd.into());
+
+        if let Some(path) = &self.submitted_path {
+            let result = std::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .and_then(|mut file| writeln!(file, "{cmd}"));
+            if let Err(err) = result {
+                warn!("Failed to save console history to {:?}: {err}", path);
+            }
+        }
+    }
+
+    /// Steps through `submitted`, `older` towards the first command. Stepping
+    /// past the newest one brings back the draft.
+    fn recall(&mut self, older: bool) {
+        let index = match (self.recall_index, older) {
+            (None, true) if !self.submitted.is_empty() => {
+                self.draft = self.command.clone();
+                self.submitted.len() - 1
+            }
+            (None, _) => return,
+            (Some(index), true) => index.saturating_sub(1),
+            (Some(index), false) if index + 1 < self.submitted.len() => index + 1,
+            (Some(_), false) => {
+                self.recall_index = None;
+                self.command = std::mem::take(&mut self.draft);
+                return;
+            }
+        };
+        self.recall_index = Some(index);
+        self.command = self.submitted[index].clone();
+    }
+
+    /// Completes the word before the cursor. The first argument is a
+    /// subcommand, later ones any known name. Several matches complete
+    /// their common prefix and get listed in the history.
+    fn complete(&mut self) {
+        let word_start = self.command.rfind(' ').map_or(0, |index| index + 1);
+        let (before, prefix) = self.command.split_at(word_start);
+        let words: Vec<&str> = before.split_whitespace().collect();
+        let lotus = ["lotus".to_string()];
+        let candidates: &[String] = match words[..] {
+            [] => &lotus,
+            ["lotus"] | ["lotus", "help"] => &self.subcommands,
+            _ => &self.completions,
+        };
+
+        let mut matches: Vec<&str> = candidates
+            .iter()
+            .map(String::as_str)
+            .filter(|candidate| candidate.starts_with(prefix))
+            .collect();
+        matches.sort_unstable();
+        matches.dedup();
+
+        let (completed, listing) = match matches[..] {
+            [] => return,
+            [only] => (format!("{only} "), None),
+            _ => (
+                common_prefix(&matches).to_string(),
+                Some(matches.join("  ")),
+            ),
+        };
+        self.command.truncate(word_start);
+        self.command += &completed;
+        if let Some(listing) = listing {
+            self.add_to_history(EntryKind::Output, &listing);
+        }
+    }
+}
+
+/// Longest prefix all of `words` share.
+fn common_prefix<'a>(words: &[&'a str]) -> &'a str {
+    let first = words[0];
+    let len = words[1..].iter().fold(first.len(), |len, word| {
+        first[..len]
+            .char_indices()
+            .zip(word.chars())
+            .find(|((_, a), b)| a != b)
+            .map_or(len.min(word.len()), |((index, _), _)| index)
+    });
+    &first[..len]
+}
+
+/// Last `MAX_RECALL` commands of the history file, trimming the file when it
+/// has grown past that.
+fn load_submitted(path: &Path) -> Vec<String> {
+    let contents = match std::fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+        Err(err) => {
+            warn!("Failed to load console history from {:?}: {err}", path);
+            return Vec::new();
+        }
+    };
+    let mut submitted: Vec<String> = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(String::from)
+        .collect();
+    if submitted.len() > MAX_RECALL {
+        submitted.drain(..submitted.len() - MAX_RECALL);
+        let mut trimmed = submitted.join("\n");
+        trimmed.push('\n');
+        if let Err(err) = std::fs::write(path, trimmed) {
+            warn!("Failed to trim console history {:?}: {err}", path);
+        }
+    }
+
+    submitted
 }
 
 impl UiNode for ConsoleNode {
     fn add_ui(&mut self, ui: &mut egui::Ui) {
         ui.with_layout(egui::Layout::bottom_up(egui::Align::Min), |ui| {
-            let re = ui.text_edit_singleline(&mut self.command);
+            let id = egui::Id::new("console_input");
+            let mut edited = false;
+            if ui.memory(|memory| memory.has_focus(id)) {
+                let consume = |key| ui.input_mut(|input| input.consume_key(Modifiers::NONE, key));
+                if consume(Key::Tab) {
+                    self.complete();
+                    edited = true;
+                }
+                if consume(Key::ArrowUp) {
+                    self.recall(true);
+                    edited = true;
+                }
+                if consume(Key::ArrowDown) {
+                    self.recall(false);
+                    edited = true;
+                }
+            }
+
+            let mut output = egui::TextEdit::singleline(&mut self.command)
+                .id(id)
+                .lock_focus(true)
+                .show(ui);
+            if edited {
+                let end = CCursor::new(self.command.chars().count());
+                output
+                    .state
+                    .cursor
+                    .set_char_range(Some(CCursorRange::one(end)));
+                output.state.store(ui.ctx(), id);
+            }
+            let re = output.response;
             if self.request_focus {
                 re.request_focus();
                 self.request_focus = false;
@@ -59,16 +333,213 @@ impl UiNode for ConsoleNode {
             let is_submitted = re.ctx.input(|x| x.key_down(egui::Key::Enter));
 
             if is_submitted && re.lost_focus() {
-                let cmd = self.command.clone();
-                self.add_to_history(&cmd);
-                self.proxy
-                    .send_event(CustomEvents::UserCommand(cmd))
-                    .unwrap();
-                self.command.clear();
+                self.submit();
                 re.request_focus();
             }
-            let text = RichText::new(&self.history).color(Color32::BLACK);
-            let _re = ui.label(text);
+
+            let search = self.search.to_lowercase();
+            let visible: Vec<&ConsoleEntry> = self
+                .history
+                .iter()
+                .filter(|entry| search.is_empty() || entry.text.to_lowercase().contains(&search))
+                .collect();
+
+            ui.horizontal(|ui| {
+                ui.add(egui::TextEdit::singleline(&mut self.search).hint_text("Search"));
+                if ui.button("Copy").clicked() {
+                    let lines: Vec<String> = visible.iter().map(|entry| entry.to_line()).collect();
+                    ui.output_mut(|output| output.copied_text = lines.join("\n"));
+                }
+            });
+
+            let font_id = egui::TextStyle::Body.resolve(ui.style());
+            egui::ScrollArea::vertical()
+                .auto_shrink([false, false])
+                .stick_to_bottom(true)
+                .show(ui, |ui| {
+                    ui.with_layout(egui::Layout::top_down(egui::Align::Min), |ui| {
+                        for entry in visible {
+                            let mut text = LayoutJob::default();
+                            let time = format!("{} ", entry.timestamp());
+                            text.append(
+                                &time,
+                                0.0,
+                                TextFormat::simple(font_id.clone(), Color32::GRAY),
+                            );
+                            if entry.kind == EntryKind::Input {
+                                text.append(
+                                    "> ",
+                                    0.0,
+                                    TextFormat::simple(font_id.clone(), entry.kind.color()),
+                                );
+                            }
+                            text.append(
+                                &entry.text,
+                                0.0,
+                                TextFormat::simple(font_id.clone(), entry.kind.color()),
+                            );
+                            ui.label(text);
+                        }
+                    });
+                });
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn console() -> ConsoleNode {
+        let mut console = ConsoleNode::new(None, None);
+        console.set_completions(
+            [
+                "render.wireframe",
+                "render.show_noise",
+                "ui.show_fps",
+                "plane",
+            ]
+            .map(String::from)
+            .to_vec(),
+        );
+        console
+    }
+
+    fn complete(console: &mut ConsoleNode, command: &str) -> String {
+        console.command = command.into();
+        console.complete();
+        console.command.clone()
+    }
+
+    fn last_entry(console: &ConsoleNode) -> Option<&str> {
+        console.history.back().map(|entry| entry.text.as_str())
+    }
+
+    #[test]
+    fn completes_a_single_match_with_a_space() {
+        let mut console = console();
+        assert_eq!(complete(&mut console, "lo"), "lotus ");
+        assert_eq!(complete(&mut console, "lotus sp"), "lotus spawn ");
+        assert_eq!(
+            complete(&mut console, "lotus set ui"),
+            "lotus set ui.show_fps "
+        );
+        assert!(console.history.is_empty());
+    }
+
+    #[test]
+    fn completes_the_common_prefix_of_several_matches() {
+        let mut console = console();
+        assert_eq!(complete(&mut console, "lotus set re"), "lotus set render.");
+        assert_eq!(
+            last_entry(&console),
+            Some("render.show_noise  render.wireframe")
+        );
+
+        assert_eq!(complete(&mut console, "lotus s"), "lotus s");
+        assert_eq!(last_entry(&console), Some("scale  set  spawn"));
+    }
+
+    #[test]
+    fn leaves_the_command_without_matches() {
+        let mut console = console();
+        assert_eq!(complete(&mut console, "lotus set nope"), "lotus set nope");
+        assert_eq!(complete(&mut console, "lotus zz"), "lotus zz");
+        assert_eq!(complete(&mut console, "echo"), "echo");
+        assert!(console.history.is_empty());
+    }
+
+    #[test]
+    fn common_prefix_stops_at_characters() {
+        assert_eq!(common_prefix(&["abc"]), "abc");
+        assert_eq!(common_prefix(&["abcd", "abce", "abx"]), "ab");
+        assert_eq!(common_prefix(&["abc", "ab"]), "ab");
+        assert_eq!(common_prefix(&["xyz", "abc"]), "");
+        // 'é' and 'è' share their first byte.
+        assert_eq!(common_prefix(&["café", "cafè"]), "caf");
+    }
+
+    #[test]
+    fn recalls_older_commands_and_stops_at_the_ends() {
+        let mut console = console();
+        for cmd in ["lotus get a", "lotus get b", "lotus get c"] {
+            console.remember(cmd);
+        }
+
+        console.recall(false);
+        assert_eq!(console.command, "");
+        console.recall(true);
+        assert_eq!(console.command, "lotus get c");
+        console.recall(true);
+        console.recall(true);
+        assert_eq!(console.command, "lotus get a");
+        console.recall(true);
+        assert_eq!(console.command, "lotus get a");
+        console.recall(false);
+        assert_eq!(console.command, "lotus get b");
+    }
+
+    #[test]
+    fn brings_back_the_draft_past_the_newest_command() {
+        let mut console = console();
+        console.remember("lotus get a");
+        console.remember("lotus get b");
+        console.command = "lotus spa".into();
+
+        console.recall(true);
+        console.recall(true);
+        assert_eq!(console.command, "lotus get a");
+        console.recall(false);
+        console.recall(false);
+        assert_eq!(console.command, "lotus spa");
+        assert_eq!(console.recall_index, None);
+        console.recall(false);
+        assert_eq!(console.command, "lotus spa");
+    }
+
+    #[test]
+    fn remembers_at_most_max_recall_commands() {
+        let mut console = console();
+        console.remember("lotus get 0");
+        console.remember("lotus get 0");
+        console.remember("   ");
+        assert_eq!(console.submitted, ["lotus get 0"]);
+
+        for index in 1..=MAX_RECALL {
+            console.remember(&format!("lotus get {index}"));
+        }
+        assert_eq!(console.submitted.len(), MAX_RECALL);
+        assert_eq!(console.submitted[0], "lotus get 1");
+        for _ in 0..MAX_RECALL + 5 {
+            console.recall(true);
+        }
+        assert_eq!(console.command, "lotus get 1");
+    }
+
+    #[test]
+    fn drops_the_oldest_entries_at_capacity() {
+        let mut console = console();
+        for index in 0..HISTORY_CAPACITY + 3 {
+            console.add_to_history(EntryKind::Output, &index.to_string());
+        }
+
+        assert_eq!(console.history.len(), HISTORY_CAPACITY);
+        assert_eq!(console.history[0].text, "3");
+        assert_eq!(
+            last_entry(&console),
+            Some((HISTORY_CAPACITY + 2).to_string().as_str())
+        );
+    }
+
+    #[test]
+    fn keeps_multi_byte_text_whole() {
+        let mut console = console();
+        console.add_to_history(EntryKind::Error, "chunk 🧱 ist über\u{3000}\n");
+        console.add_to_history(EntryKind::Info, "日本語のテキスト");
+
+        assert_eq!(console.history[0].text, "chunk 🧱 ist über");
+        let line = console.history[0].to_line();
+        assert!(line.ends_with(" error: chunk 🧱 ist über"), "{line}");
+        assert!(console.history[1].to_line().ends_with(" 日本語のテキスト"));
+    }
+}
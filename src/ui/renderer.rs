@@ -19,6 +19,16 @@ impl UiRenderer {
         self.state.egui_ctx()
     }
 
+    /// Scales every text style so body text is `size` points.
+    pub fn set_font_size(&self, size: f32) {
+        self.context().style_mut(|style| {
+            let scale = size / style.text_styles[&egui::TextStyle::Body].size;
+            for font_id in style.text_styles.values_mut() {
+                font_id.size *= scale;
+            }
+        });
+    }
+
     pub fn new(
         window: &winit::window::Window,
         device: &wgpu::Device,
@@ -2,7 +2,6 @@ use log::info;
 
 use super::renderer::UiNode;
 
-#[derive(Default)]
 pub struct SettingsNode {
     pub show_fps: bool,
     pub show_wireframe: bool,
@@ -10,6 +9,24 @@ pub struct SettingsNode {
     pub show_noise: bool,
     pub noise_seed: f32,
     pub save_noise_texture: bool,
+    pub noise_save_path: String,
+    /// Set when the ui changes a saved setting, cleared once it's stored.
+    pub changed: bool,
+}
+
+impl Default for SettingsNode {
+    fn default() -> Self {
+        Self {
+            show_fps: false,
+            show_wireframe: false,
+            full_screen: false,
+            show_noise: false,
+            noise_seed: 0.0,
+            save_noise_texture: false,
+            noise_save_path: "noise.png".into(),
+            changed: false,
+        }
+    }
 }
 
 impl UiNode for SettingsNode {
@@ -19,13 +36,21 @@ impl UiNode for SettingsNode {
             .color(egui::Color32::WHITE);
 
         ui.label(settings_header);
-        ui.toggle_value(&mut self.show_fps, "Show FPS");
-        ui.toggle_value(&mut self.show_wireframe, "Show Wireframe");
+        let mut changed = ui.toggle_value(&mut self.show_fps, "Show FPS").changed();
+        changed |= ui
+            .toggle_value(&mut self.show_wireframe, "Show Wireframe")
+            .changed();
         let noise_state = self.show_noise;
-        ui.toggle_value(&mut self.save_noise_texture, "Save Noise Texture");
+        ui.horizontal(|ui| {
+            ui.toggle_value(&mut self.save_noise_texture, "Save Noise Texture");
+            changed |= ui.text_edit_singleline(&mut self.noise_save_path).changed();
+        });
 
         ui.toggle_value(&mut self.show_noise, "Show Noise");
-        ui.toggle_value(&mut self.full_screen, "FullScreen");
+        changed |= ui
+            .toggle_value(&mut self.full_screen, "FullScreen")
+            .changed();
+        self.changed |= changed;
 
         if self.show_noise && noise_state == false {
             self.noise_seed = rand::random();
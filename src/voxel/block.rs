@@ -0,0 +1,78 @@
+use std::str::FromStr;
+
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize,
+)]
+pub enum Block {
+    #[default]
+    Air,
+    Stone,
+    Dirt,
+    Grass,
+    Water,
+}
+
+impl Block {
+    pub const ALL: [Block; 5] = [
+        Block::Air,
+        Block::Stone,
+        Block::Dirt,
+        Block::Grass,
+        Block::Water,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Block::Air => "air",
+            Block::Stone => "stone",
+            Block::Dirt => "dirt",
+            Block::Grass => "grass",
+            Block::Water => "water",
+        }
+    }
+
+    pub fn is_air(&self) -> bool {
+        matches!(self, Block::Air)
+    }
+
+    pub fn is_transparent(&self) -> bool {
+        matches!(self, Block::Air | Block::Water)
+    }
+
+    /// Whether the face of this block touching `neighbour` should be drawn.
+    pub fn is_face_visible(&self, neighbour: Block) -> bool {
+        !self.is_air() && neighbour.is_transparent() && neighbour != *self
+    }
+
+    /// Layer of the block texture array used for the given face.
+    pub fn texture_layer(&self, face: BlockFace) -> u32 {
+        match (self, face) {
+            (Block::Air, _) => 0,
+            (Block::Stone, _) => 0,
+            (Block::Dirt, _) | (Block::Grass, BlockFace::Bottom) => 1,
+            (Block::Grass, BlockFace::Top) => 2,
+            (Block::Grass, BlockFace::Side) => 3,
+            (Block::Water, _) => 4,
+        }
+    }
+}
+
+pub const BLOCK_TEXTURE_LAYERS: u32 = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockFace {
+    Top,
+    Bottom,
+    Side,
+}
+
+impl FromStr for Block {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Block::ALL
+            .into_iter()
+            .find(|block| block.name() == s)
+            .ok_or_else(|| format!("Unknown block '{s}'"))
+    }
+}
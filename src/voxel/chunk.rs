@@ -0,0 +1,389 @@
+use super::block::Block;
+
+pub const CHUNK_SIZE: usize = 32;
+pub const CHUNK_AREA: usize = CHUNK_SIZE * CHUNK_SIZE;
+pub const CHUNK_VOLUME: usize = CHUNK_AREA * CHUNK_SIZE;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, serde::Serialize, serde::Deserialize)]
+pub struct ChunkPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl ChunkPos {
+    pub fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    /// Chunk containing the block at world coordinates `(x, y, z)`, along with
+    /// the block's local coordinates inside that chunk.
+    pub fn from_world(x: i32, y: i32, z: i32) -> (Self, (usize, usize, usize)) {
+        let size = CHUNK_SIZE as i32;
+        let pos = Self::new(x.div_euclid(size), y.div_euclid(size), z.div_euclid(size));
+        let local = (
+            x.rem_euclid(size) as usize,
+            y.rem_euclid(size) as usize,
+            z.rem_euclid(size) as usize,
+        );
+
+        (pos, local)
+    }
+
+    /// World coordinates of the chunk's minimum corner.
+    pub fn origin(&self) -> (i32, i32, i32) {
+        let size = CHUNK_SIZE as i32;
+        (self.x * size, self.y * size, self.z * size)
+    }
+
+    pub fn offset(&self, dx: i32, dy: i32, dz: i32) -> Self {
+        Self::new(self.x + dx, self.y + dy, self.z + dz)
+    }
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct PaletteEntry {
+    block: Block,
+    count: u32,
+}
+
+/// Widths indices are packed with, they divide a word evenly.
+const INDEX_BITS: [u32; 6] = [0, 1, 2, 4, 8, 16];
+
+/// Palette index of every block, `bits` wide and packed into words.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+struct Indices {
+    bits: u32,
+    words: Vec<u64>,
+}
+
+impl Indices {
+    fn new(bits: u32) -> Self {
+        Self {
+            bits,
+            words: vec![0; Self::word_count(bits)],
+        }
+    }
+
+    fn word_count(bits: u32) -> usize {
+        CHUNK_VOLUME * bits as usize / u64::BITS as usize
+    }
+
+    /// Narrowest width that fits the indices of a palette of `len` entries.
+    fn bits_for(len: usize) -> u32 {
+        INDEX_BITS
+            .into_iter()
+            .find(|&bits| len <= 1 << bits)
+            .expect("palette larger than u16 indices")
+    }
+
+    fn get(&self, index: usize) -> usize {
+        if self.bits == 0 {
+            return 0;
+        }
+        let per_word = (u64::BITS / self.bits) as usize;
+        let shift = (index % per_word) as u32 * self.bits;
+        ((self.words[index / per_word] >> shift) & self.mask()) as usize
+    }
+
+    fn set(&mut self, index: usize, value: usize) {
+        debug_assert!(value as u64 <= self.mask());
+        if self.bits == 0 {
+            return;
+        }
+        let per_word = (u64::BITS / self.bits) as usize;
+        let shift = (index % per_word) as u32 * self.bits;
+        let mask = self.mask() << shift;
+        let word = &mut self.words[index / per_word];
+        *word = (*word & !mask) | ((value as u64) << shift);
+    }
+
+    fn mask(&self) -> u64 {
+        (1 << self.bits) - 1
+    }
+
+    /// The same indices packed `bits` wide.
+    fn widened(&self, bits: u32) -> Self {
+        let mut widened = Self::new(bits);
+        for index in 0..CHUNK_VOLUME {
+            widened.set(index, self.get(index));
+        }
+
+        widened
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum ChunkError {
+    #[error("Chunk indices are {0} bits wide, expected one of {INDEX_BITS:?}")]
+    IndexBits(u32),
+    #[error("Chunk has {found} index words, expected {expected}")]
+    IndexWords { expected: usize, found: usize },
+    #[error("Block index {index} is past the chunk palette of {len}")]
+    IndexOutOfPalette { index: usize, len: usize },
+    #[error("Chunk palette has {0:?} more than once")]
+    DuplicateBlock(Block),
+    #[error("Chunk palette counts {count} {block:?} blocks, found {found}")]
+    PaletteCount {
+        block: Block,
+        count: u32,
+        found: u32,
+    },
+}
+
+/// A `CHUNK_SIZE`³ block of voxels. Blocks are stored as indices into a
+/// per-chunk palette, packed only as wide as the palette needs, so chunks
+/// only pay for the block types they contain.
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(try_from = "ChunkData")]
+pub struct Chunk {
+    palette: Vec<PaletteEntry>,
+    blocks: Indices,
+}
+
+/// `Chunk` as it's serialized, checked before it becomes one.
+#[derive(serde::Deserialize)]
+struct ChunkData {
+    palette: Vec<PaletteEntry>,
+    blocks: Indices,
+}
+
+impl TryFrom<ChunkData> for Chunk {
+    type Error = ChunkError;
+
+    fn try_from(data: ChunkData) -> Result<Self, Self::Error> {
+        let ChunkData { palette, blocks } = data;
+        if !INDEX_BITS.contains(&blocks.bits) {
+            return Err(ChunkError::IndexBits(blocks.bits));
+        }
+        let expected = Indices::word_count(blocks.bits);
+        if blocks.words.len() != expected {
+            return Err(ChunkError::IndexWords {
+                expected,
+                found: blocks.words.len(),
+            });
+        }
+
+        let mut counts = vec![0; palette.len()];
+        for index in 0..CHUNK_VOLUME {
+            let entry = blocks.get(index);
+            let count = counts.get_mut(entry).ok_or(ChunkError::IndexOutOfPalette {
+                index: entry,
+                len: palette.len(),
+            })?;
+            *count += 1;
+        }
+        for (index, entry) in palette.iter().enumerate() {
+            if palette[..index]
+                .iter()
+                .any(|other| other.block == entry.block)
+            {
+                return Err(ChunkError::DuplicateBlock(entry.block));
+            }
+            if entry.count != counts[index] {
+                return Err(ChunkError::PaletteCount {
+                    block: entry.block,
+                    count: entry.count,
+                    found: counts[index],
+                });
+            }
+        }
+
+        Ok(Self { palette, blocks })
+    }
+}
+
+impl Default for Chunk {
+    fn default() -> Self {
+        Self::filled(Block::Air)
+    }
+}
+
+impl Chunk {
+    pub fn filled(block: Block) -> Self {
+        Self {
+            palette: vec![PaletteEntry {
+                block,
+                count: CHUNK_VOLUME as u32,
+            }],
+            blocks: Indices::new(0),
+        }
+    }
+
+    pub fn index(x: usize, y: usize, z: usize) -> usize {
+        debug_assert!(x < CHUNK_SIZE && y < CHUNK_SIZE && z < CHUNK_SIZE);
+        x + z * CHUNK_SIZE + y * CHUNK_AREA
+    }
+
+    pub fn get(&self, x: usize, y: usize, z: usize) -> Block {
+        self.palette[self.blocks.get(Self::index(x, y, z))].block
+    }
+
+    /// Sets the block at local coordinates and returns the block it replaced.
+    pub fn set(&mut self, x: usize, y: usize, z: usize, block: Block) -> Block {
+        let index = Self::index(x, y, z);
+        let old_entry = self.blocks.get(index);
+        let old_block = self.palette[old_entry].block;
+        if old_block == block {
+            return old_block;
+        }
+
+        self.palette[old_entry].count -= 1;
+        let new_entry = self.palette_entry(block);
+        self.palette[new_entry].count += 1;
+        if new_entry as u64 > self.blocks.mask() {
+            self.blocks = self.blocks.widened(Indices::bits_for(self.palette.len()));
+        }
+        self.blocks.set(index, new_entry);
+
+        old_block
+    }
+    fn palette_entry(&mut self, block: Block) -> usize {
+        if let Some(idx) = self.palette.iter().position(|entry| entry.block == block) {
+            return idx;
+        }
+        let entry = PaletteEntry { block, count: 0 };
+        if let Some(idx) = self.palette.iter().position(|entry| entry.count == 0) {
+            self.palette[idx] = entry;
+            return idx;
+        }
+        self.palette.push(entry);
+
+        self.palette.len() - 1
+    }
+
+    /// Block types currently present in the chunk.
+    pub fn palette(&self) -> impl Iterator<Item = Block> + '_ {
+        self.palette
+            .iter()
+            .filter(|entry| entry.count > 0)
+            .map(|entry| entry.block)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.palette().all(|block| block.is_air())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_world_rounds_negative_coordinates_down() {
+        assert_eq!(
+            ChunkPos::from_world(-1, 0, 31),
+            (ChunkPos::new(-1, 0, 0), (31, 0, 31))
+        );
+        assert_eq!(
+            ChunkPos::from_world(-32, -33, 32),
+            (ChunkPos::new(-1, -2, 1), (0, 31, 0))
+        );
+        assert_eq!(ChunkPos::new(-1, -2, 1).origin(), (-32, -64, 32));
+    }
+
+    #[test]
+    fn set_and_get_round_trip() {
+        let mut chunk = Chunk::default();
+        assert_eq!(chunk.set(0, 0, 0, Block::Stone), Block::Air);
+        assert_eq!(chunk.set(31, 31, 31, Block::Grass), Block::Air);
+        assert_eq!(chunk.set(5, 6, 7, Block::Water), Block::Air);
+        assert_eq!(chunk.set(5, 6, 7, Block::Dirt), Block::Water);
+
+        assert_eq!(chunk.get(0, 0, 0), Block::Stone);
+        assert_eq!(chunk.get(31, 31, 31), Block::Grass);
+        assert_eq!(chunk.get(5, 6, 7), Block::Dirt);
+        assert_eq!(chunk.get(1, 0, 0), Block::Air);
+    }
+
+    #[test]
+    fn counts_and_reuses_palette_entries() {
+        let mut chunk = Chunk::default();
+        chunk.set(0, 0, 0, Block::Stone);
+        chunk.set(1, 0, 0, Block::Stone);
+        assert_eq!(chunk.palette[1].count, 2);
+        assert_eq!(chunk.palette[0].count, CHUNK_VOLUME as u32 - 2);
+
+        chunk.set(0, 0, 0, Block::Air);
+        chunk.set(1, 0, 0, Block::Air);
+        assert_eq!(chunk.palette().collect::<Vec<_>>(), [Block::Air]);
+
+        // The freed stone entry is taken over instead of growing the palette.
+        chunk.set(2, 0, 0, Block::Dirt);
+        assert_eq!(chunk.palette.len(), 2);
+        assert_eq!(chunk.palette[1].block, Block::Dirt);
+        assert_eq!(chunk.get(2, 0, 0), Block::Dirt);
+    }
+
+    #[test]
+    fn packs_indices_as_wide_as_the_palette() {
+        let mut chunk = Chunk::filled(Block::Stone);
+        assert!(chunk.blocks.words.is_empty());
+
+        for (x, block) in Block::ALL.into_iter().enumerate() {
+            chunk.set(x, 1, 2, block);
+        }
+        assert_eq!(chunk.blocks.bits, 4);
+        assert_eq!(chunk.blocks.words.len(), CHUNK_VOLUME * 4 / 64);
+        for (x, block) in Block::ALL.into_iter().enumerate() {
+            assert_eq!(chunk.get(x, 1, 2), block);
+        }
+        assert_eq!(chunk.get(6, 1, 2), Block::Stone);
+    }
+
+    #[test]
+    fn is_empty_once_only_air_is_left() {
+        let mut chunk = Chunk::default();
+        assert!(chunk.is_empty());
+        chunk.set(3, 3, 3, Block::Stone);
+        assert!(!chunk.is_empty());
+        chunk.set(3, 3, 3, Block::Air);
+        assert!(chunk.is_empty());
+        assert!(!Chunk::filled(Block::Water).is_empty());
+    }
+
+    #[test]
+    fn deserializes_what_it_serialized() {
+        let mut chunk = Chunk::default();
+        chunk.set(0, 0, 0, Block::Stone);
+        chunk.set(9, 8, 7, Block::Grass);
+
+        let json = serde_json::to_string(&chunk).unwrap();
+        let loaded: Chunk = serde_json::from_str(&json).unwrap();
+        assert_eq!(loaded.get(0, 0, 0), Block::Stone);
+        assert_eq!(loaded.get(9, 8, 7), Block::Grass);
+        assert_eq!(loaded.get(1, 0, 0), Block::Air);
+    }
+
+    fn deserialize(mut edit: impl FnMut(&mut serde_json::Value)) -> Result<Chunk, String> {
+        let mut chunk = Chunk::default();
+        chunk.set(0, 0, 0, Block::Stone);
+        let mut value = serde_json::to_value(&chunk).unwrap();
+        edit(&mut value);
+        serde_json::from_value(value).map_err(|err| err.to_string())
+    }
+
+    #[test]
+    fn rejects_indices_past_the_palette() {
+        let err = deserialize(|value| {
+            value["palette"].as_array_mut().unwrap().pop();
+        })
+        .unwrap_err();
+        assert!(err.contains("past the chunk palette"), "{err}");
+    }
+
+    #[test]
+    fn rejects_wrong_palette_counts_and_sizes() {
+        let err = deserialize(|value| value["palette"][1]["count"] = 2.into()).unwrap_err();
+        assert!(err.contains("counts 2 Stone blocks, found 1"), "{err}");
+        let err = deserialize(|value| value["palette"][1]["block"] = "Air".into()).unwrap_err();
+        assert!(err.contains("more than once"), "{err}");
+        let err = deserialize(|value| value["blocks"]["bits"] = 3.into()).unwrap_err();
+        assert!(err.contains("3 bits wide"), "{err}");
+        let err = deserialize(|value| {
+            value["blocks"]["words"].as_array_mut().unwrap().pop();
+        })
+        .unwrap_err();
+        assert!(err.contains("index words"), "{err}");
+    }
+}
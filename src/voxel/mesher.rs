@@ -0,0 +1,272 @@
+use super::{
+    block::{Block, BlockFace},
+    chunk::{ChunkPos, CHUNK_AREA, CHUNK_SIZE},
+    vertex::ModelVertex,
+    world::VoxelWorld,
+};
+
+const PADDED_SIZE: usize = CHUNK_SIZE + 2;
+
+#[derive(Debug, Default, Clone)]
+pub struct ChunkMeshData {
+    pub vertices: Vec<ModelVertex>,
+    pub indices: Vec<u32>,
+}
+
+impl ChunkMeshData {
+    pub fn quad_count(&self) -> usize {
+        self.indices.len() / 6
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+}
+
+/// Chunk blocks plus a one block border taken from the six face neighbours,
+/// so face culling across chunk boundaries doesn't need any lookups.
+struct PaddedChunk {
+    blocks: Vec<Block>,
+}
+
+impl PaddedChunk {
+    fn new(world: &VoxelWorld, pos: ChunkPos) -> Option<Self> {
+        let chunk = world.chunk(pos)?;
+        let size = CHUNK_SIZE as i32;
+        let mut blocks = vec![Block::Air; PADDED_SIZE * PADDED_SIZE * PADDED_SIZE];
+
+        for y in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    blocks[Self::index([x as i32, y as i32, z as i32])] = chunk.get(x, y, z);
+                }
+            }
+        }
+
+        for d in 0..3 {
+            let u = (d + 1) % 3;
+            let v = (d + 2) % 3;
+            for (step, depth, neighbour_depth) in [(-1, -1, size - 1), (1, size, 0)] {
+                let mut offset = [0; 3];
+                offset[d] = step;
+                let Some(neighbour) = world.chunk(pos.offset(offset[0], offset[1], offset[2]))
+                else {
+                    continue;
+                };
+                for j in 0..size {
+                    for i in 0..size {
+                        let mut p = [0; 3];
+                        p[u] = i;
+                        p[v] = j;
+                        p[d] = neighbour_depth;
+                        let block = neighbour.get(p[0] as usize, p[1] as usize, p[2] as usize);
+                        p[d] = depth;
+                        blocks[Self::index(p)] = block;
+                    }
+                }
+            }
+        }
+
+        Some(Self { blocks })
+    }
+
+    fn index(p: [i32; 3]) -> usize {
+        let [x, y, z] = p.map(|c| (c + 1) as usize);
+        x + z * PADDED_SIZE + y * PADDED_SIZE * PADDED_SIZE
+    }
+
+    fn get(&self, p: [i32; 3]) -> Block {
+        self.blocks[Self::index(p)]
+    }
+}
+
+/// Builds a single mesh for the chunk at `pos`, merging coplanar faces with
+/// the same texture layer into larger quads and skipping faces hidden by
+/// neighbouring blocks, including ones in adjacent chunks.
+/// Returns `None` if the chunk isn't loaded.
+pub fn mesh_chunk(world: &VoxelWorld, pos: ChunkPos) -> Option<ChunkMeshData> {
+    let padded = PaddedChunk::new(world, pos)?;
+    let (ox, oy, oz) = pos.origin();
+    let origin = [ox as f32, oy as f32, oz as f32];
+    let mut mesh = ChunkMeshData::default();
+    let mut mask: Vec<Option<u32>> = vec![None; CHUNK_AREA];
+
+    for d in 0..3 {
+        let u = (d + 1) % 3;
+        let v = (d + 2) % 3;
+        for positive in [false, true] {
+            let face = match (d, positive) {
+                (1, true) => BlockFace::Top,
+                (1, false) => BlockFace::Bottom,
+                _ => BlockFace::Side,
+            };
+            for slice in 0..CHUNK_SIZE {
+                for j in 0..CHUNK_SIZE {
+                    for i in 0..CHUNK_SIZE {
+                        let mut p = [0; 3];
+                        p[d] = slice as i32;
+                        p[u] = i as i32;
+                        p[v] = j as i32;
+                        let block = padded.get(p);
+                        p[d] += if positive { 1 } else { -1 };
+                        let neighbour = padded.get(p);
+
+                        mask[i + j * CHUNK_SIZE] = block
+                            .is_face_visible(neighbour)
+                            .then(|| block.texture_layer(face));
+                    }
+                }
+
+                for j in 0..CHUNK_SIZE {
+                    let mut i = 0;
+                    while i < CHUNK_SIZE {
+                        let Some(layer) = mask[i + j * CHUNK_SIZE] else {
+                            i += 1;
+                            continue;
+                        };
+
+                        let mut w = 1;
+                        while i + w < CHUNK_SIZE && mask[i + w + j * CHUNK_SIZE] == Some(layer) {
+                            w += 1;
+                        }
+                        let mut h = 1;
+                        'grow: while j + h < CHUNK_SIZE {
+                            for k in 0..w {
+                                if mask[i + k + (j + h) * CHUNK_SIZE] != Some(layer) {
+                                    break 'grow;
+                                }
+                            }
+                            h += 1;
+                        }
+
+                        for dj in 0..h {
+                            for di in 0..w {
+                                mask[i + di + (j + dj) * CHUNK_SIZE] = None;
+                            }
+                        }
+
+                        let quad = Quad {
+                            axes: [d, u, v],
+                            depth: slice + positive as usize,
+                            start: [i, j],
+                            size: [w, h],
+                            positive,
+                            layer,
+                        };
+                        quad.emit(origin, &mut mesh);
+                        i += w;
+                    }
+                }
+            }
+        }
+    }
+
+    Some(mesh)
+}
+
+struct Quad {
+    /// Normal axis followed by the two axes spanning the quad.
+    axes: [usize; 3],
+    depth: usize,
+    start: [usize; 2],
+    size: [usize; 2],
+    positive: bool,
+    layer: u32,
+}
+
+impl Quad {
+    fn emit(&self, origin: [f32; 3], mesh: &mut ChunkMeshData) {
+        let [d, u, v] = self.axes;
+        let [w, h] = self.size.map(|s| s as f32);
+        let mut normal = [0.0; 3];
+        normal[d] = if self.positive { 1.0 } else { -1.0 };
+
+        let base_index = mesh.vertices.len() as u32;
+        for (du, dv) in [(0.0, 0.0), (w, 0.0), (w, h), (0.0, h)] {
+            let mut position = origin;
+            position[d] += self.depth as f32;
+            position[u] += self.start[0] as f32 + du;
+            position[v] += self.start[1] as f32 + dv;
+
+            // Keep textures upright on side faces, +y is always up.
+            let tex_coords = match d {
+                0 => [dv, w - du],
+                1 => [dv, du],
+                _ => [du, h - dv],
+            };
+
+            mesh.vertices.push(ModelVertex {
+                position,
+                tex_coords,
+                normal,
+                layer: self.layer,
+            });
+        }
+
+        // u x v points along +d, so the corners are counter clockwise when
+        // seen from the positive side.
+        let order: [u32; 6] = if self.positive {
+            [0, 1, 2, 0, 2, 3]
+        } else {
+            [0, 2, 1, 0, 3, 2]
+        };
+        mesh.indices
+            .extend(order.into_iter().map(|idx| base_index + idx));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::voxel::chunk::Chunk;
+
+    fn quads(world: &VoxelWorld, pos: ChunkPos) -> usize {
+        mesh_chunk(world, pos).unwrap().quad_count()
+    }
+
+    #[test]
+    fn single_block_has_six_quads() {
+        let mut world = VoxelWorld::default();
+        world.set_block(3, 4, 5, Block::Stone);
+
+        assert_eq!(quads(&world, ChunkPos::new(0, 0, 0)), 6);
+    }
+
+    #[test]
+    fn full_chunk_merges_into_six_quads() {
+        let mut world = VoxelWorld::default();
+        world.insert_chunk(ChunkPos::new(0, 0, 0), Chunk::filled(Block::Stone));
+
+        assert_eq!(quads(&world, ChunkPos::new(0, 0, 0)), 6);
+    }
+
+    #[test]
+    fn faces_between_chunks_are_hidden() {
+        let mut world = VoxelWorld::default();
+        world.insert_chunk(ChunkPos::new(0, 0, 0), Chunk::filled(Block::Stone));
+        world.insert_chunk(ChunkPos::new(1, 0, 0), Chunk::filled(Block::Stone));
+
+        assert_eq!(quads(&world, ChunkPos::new(0, 0, 0)), 5);
+        assert_eq!(quads(&world, ChunkPos::new(1, 0, 0)), 5);
+    }
+
+    #[test]
+    fn checkerboard_merges_nothing() {
+        let mut chunk = Chunk::default();
+        let mut solid = 0;
+        for y in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    if (x + y + z) % 2 == 0 {
+                        chunk.set(x, y, z, Block::Stone);
+                        solid += 1;
+                    }
+                }
+            }
+        }
+        let mut world = VoxelWorld::default();
+        world.insert_chunk(ChunkPos::new(0, 0, 0), chunk);
+
+        assert_eq!(quads(&world, ChunkPos::new(0, 0, 0)), solid * 6);
+    }
+}
@@ -1,7 +1,12 @@
+pub mod block;
+pub mod chunk;
 pub mod instance;
 pub mod light;
+pub mod mesher;
 pub mod model;
 pub mod plane;
 pub mod renderer;
+pub mod terrain;
 pub mod texture;
 pub mod vertex;
+pub mod world;
@@ -13,7 +13,16 @@ use crate::{
     engine_state::{EngineState, TextureWithView},
 };
 
-use super::{texture, vertex::ModelVertex};
+use super::{plane::PrimitiveShape, texture, vertex::ModelVertex};
+
+// Asset handles have to stay sendable between threads.
+const _: () = {
+    const fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<Res<Model>>();
+    assert_send_sync::<Res<Mesh>>();
+    assert_send_sync::<Res<Material>>();
+    assert_send_sync::<Res<TextureWithView>>();
+};
 
 #[derive(Debug)]
 pub struct Model {
@@ -82,6 +91,33 @@ pub struct Mesh {
 }
 
 impl Mesh {
+    pub fn from_primitive<T: PrimitiveShape>(
+        device: &wgpu::Device,
+        name: impl Into<String>,
+        material: Res<Material>,
+    ) -> Self {
+        let name: String = name.into();
+        let indices: Vec<u32> = T::indices().iter().map(|i| *i as u32).collect();
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(format!("vertex_buffer_{}", name).as_str()),
+            contents: bytemuck::cast_slice(T::vertices()),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(format!("index_buffer_{}", name).as_str()),
+            contents: bytemuck::cast_slice(&indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+
+        Self {
+            name,
+            vertex_buffer,
+            index_buffer,
+            num_elements: indices.len() as _,
+            material,
+        }
+    }
+
     pub fn draw<'a>(
         &'a self,
         instances: Range<u32>,
@@ -203,6 +239,16 @@ impl Model {
                         m.mesh.positions[i * 3 + 2],
                     ],
                     tex_coords: [m.mesh.texcoords[i * 2], 1.0 - m.mesh.texcoords[i * 2 + 1]],
+                    normal: if m.mesh.normals.len() >= (i + 1) * 3 {
+                        [
+                            m.mesh.normals[i * 3],
+                            m.mesh.normals[i * 3 + 1],
+                            m.mesh.normals[i * 3 + 2],
+                        ]
+                    } else {
+                        [0.0; 3]
+                    },
+                    layer: 0,
                 };
 
                 vertices.push(vertex);
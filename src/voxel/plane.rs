@@ -12,18 +12,26 @@ const VERTICES: &[ModelVertex] = &[
     ModelVertex {
         position: [0.5, -0.5, 0.0],
         tex_coords: [1.0, 0.0],
+        normal: [0.0, 0.0, 1.0],
+        layer: 0,
     }, // A
     ModelVertex {
         position: [0.5, 0.5, 0.0],
         tex_coords: [1.0, 1.0],
+        normal: [0.0, 0.0, 1.0],
+        layer: 0,
     }, // B
     ModelVertex {
         position: [-0.5, 0.5, 0.0],
         tex_coords: [0.0, 1.0],
+        normal: [0.0, 0.0, 1.0],
+        layer: 0,
     }, // C
     ModelVertex {
         position: [-0.5, -0.5, 0.0],
         tex_coords: [0.0, 0.0],
+        normal: [0.0, 0.0, 1.0],
+        layer: 0,
     }, // D
 ];
 
@@ -1,33 +1,37 @@
-use egui_wgpu::RenderState;
-use wgpu::{
-    util::DeviceExt, BindGroupLayout, BindGroupLayoutDescriptor, BindGroupLayoutEntry,
-    BufferUsages, ShaderStages,
-};
+use std::collections::HashMap;
+
+use cgmath::Vector3;
+use wgpu::{util::DeviceExt, BindGroup, Device, Queue, RenderPass};
 
 use crate::{
-    state::create_render_pipeline,
-    voxel::{
-        instance::InstanceRaw,
-        model::Model,
-        vertex::{ModelVertex, Vertex},
-    },
+    camera::Frustum,
+    ecs::world::{Entity, World},
+    state::{create_render_pipeline, RenderPipeLineType},
+    voxel::vertex::{ModelVertex, Vertex},
 };
 
-use super::{instance::Instance, texture};
+use super::{
+    block::BLOCK_TEXTURE_LAYERS,
+    chunk::{ChunkPos, CHUNK_SIZE},
+    mesher::ChunkMeshData,
+    texture,
+};
 
-pub struct Renderer {
-    render_pipeline: wgpu::RenderPipeline,
+const BLOCK_TEXTURE_SIZE: u32 = 16;
 
+/// GPU mesh of a chunk, on the world entity with its `ChunkPos`.
+pub struct ChunkMesh {
     vertex_buffer: wgpu::Buffer,
-    num_vertices: u32,
-
     index_buffer: wgpu::Buffer,
     num_indices: u32,
+}
 
-    instances: Vec<Instance>,
-    instance_buffer: wgpu::Buffer,
-
-    depth_texture: texture::Texture,
+pub struct Renderer {
+    render_pipeline: wgpu::RenderPipeline,
+    wireframe_render_pipeline: wgpu::RenderPipeline,
+    block_texture_bind_group: BindGroup,
+    /// Entities holding the meshes of the chunks.
+    chunk_entities: HashMap<ChunkPos, Entity>,
 }
 
 impl Renderer {
@@ -36,6 +40,7 @@ impl Renderer {
         queue: &wgpu::Queue,
         format: wgpu::TextureFormat,
         camera_bind_group_layout: &wgpu::BindGroupLayout,
+        light_bind_group_layout: &wgpu::BindGroupLayout,
     ) -> anyhow::Result<Self> {
         let texture_bind_group_layout =
             device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
@@ -45,7 +50,7 @@ impl Renderer {
                         visibility: wgpu::ShaderStages::FRAGMENT,
                         ty: wgpu::BindingType::Texture {
                             sample_type: wgpu::TextureSampleType::Float { filterable: true },
-                            view_dimension: wgpu::TextureViewDimension::D2,
+                            view_dimension: wgpu::TextureViewDimension::D2Array,
                             multisampled: false,
                         },
                         count: None,
@@ -60,29 +65,225 @@ impl Renderer {
                 label: Some("voxel_texture_bind_group_layout"),
             });
 
+        let block_texture_view = create_block_texture_array(device, queue);
+        let block_sampler = device.create_sampler(&wgpu::SamplerDescriptor {
+            label: Some("voxel_block_sampler"),
+            address_mode_u: wgpu::AddressMode::Repeat,
+            address_mode_v: wgpu::AddressMode::Repeat,
+            address_mode_w: wgpu::AddressMode::ClampToEdge,
+            mag_filter: wgpu::FilterMode::Nearest,
+            min_filter: wgpu::FilterMode::Nearest,
+            mipmap_filter: wgpu::FilterMode::Nearest,
+            ..Default::default()
+        });
+        let block_texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
+            layout: &texture_bind_group_layout,
+            entries: &[
+                wgpu::BindGroupEntry {
+                    binding: 0,
+                    resource: wgpu::BindingResource::TextureView(&block_texture_view),
+                },
+                wgpu::BindGroupEntry {
+                    binding: 1,
+                    resource: wgpu::BindingResource::Sampler(&block_sampler),
+                },
+            ],
+            label: Some("voxel_block_bind_group"),
+        });
+
         let render_pipeline_layout =
             device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                label: Some("Render Pipeline Layout"),
-                bind_group_layouts: &[&texture_bind_group_layout, &camera_bind_group_layout],
+                label: Some("Voxel Render Pipeline Layout"),
+                bind_group_layouts: &[
+                    &texture_bind_group_layout,
+                    camera_bind_group_layout,
+                    light_bind_group_layout,
+                ],
                 push_constant_ranges: &[],
             });
         let shader_code = tokio::fs::read_to_string("assets/shaders/voxel_shader.wgsl").await?;
         let shader = wgpu::ShaderModuleDescriptor {
-            label: Some("Model Shader"),
+            label: Some("Voxel Shader"),
             source: wgpu::ShaderSource::Wgsl(shader_code.into()),
         };
         let shader = device.create_shader_module(shader);
 
-        // let obj_model = Model::load_obj_model_from_file_path(
-        //     "assets/models/plane_cube.obj".into(),
-        //     device,
-        //     queue,
-        //     &texture_bind_group_layout,
-        // )
-        // .await?;
+        let render_pipeline = create_render_pipeline(
+            device,
+            &render_pipeline_layout,
+            format,
+            Some(texture::Texture::DEPTH_FORMAT),
+            &[ModelVertex::desc()],
+            &shader,
+            "Voxel Render Pipeline",
+            RenderPipeLineType::Default,
+        );
+        let wireframe_render_pipeline = create_render_pipeline(
+            device,
+            &render_pipeline_layout,
+            format,
+            Some(texture::Texture::DEPTH_FORMAT),
+            &[ModelVertex::desc()],
+            &shader,
+            "Voxel Wireframe Render Pipeline",
+            RenderPipeLineType::Wireframe,
+        );
+
+        Ok(Self {
+            render_pipeline,
+            wireframe_render_pipeline,
+            block_texture_bind_group,
+            chunk_entities: HashMap::new(),
+        })
+    }
+
+    /// Replaces the GPU mesh of the chunk, empty meshes free it instead.
+    pub fn upload_chunk(
+        &mut self,
+        world: &mut World,
+        device: &Device,
+        pos: ChunkPos,
+        mesh: &ChunkMeshData,
+    ) {
+        if mesh.is_empty() {
+            self.free_chunk(world, pos);
+            return;
+        }
+
+        let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(format!("chunk_vertex_buffer_{:?}", pos).as_str()),
+            contents: bytemuck::cast_slice(&mesh.vertices),
+            usage: wgpu::BufferUsages::VERTEX,
+        });
+        let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: Some(format!("chunk_index_buffer_{:?}", pos).as_str()),
+            contents: bytemuck::cast_slice(&mesh.indices),
+            usage: wgpu::BufferUsages::INDEX,
+        });
+        let chunk_mesh = ChunkMesh {
+            vertex_buffer,
+            index_buffer,
+            num_indices: mesh.indices.len() as _,
+        };
+
+        match self.chunk_entities.get(&pos) {
+            Some(&entity) => {
+                world.add(entity, chunk_mesh).unwrap();
+            }
+            None => {
+                let entity = world.spawn((pos, chunk_mesh));
+                self.chunk_entities.insert(pos, entity);
+            }
+        }
+    }
+
+    pub fn free_chunk(&mut self, world: &mut World, pos: ChunkPos) {
+        if let Some(entity) = self.chunk_entities.remove(&pos) {
+            world.despawn(entity);
+        }
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunk_entities.len()
+    }
+
+    /// Draws the chunk meshes of `world` inside the frustum.
+    pub fn render<'a>(
+        &'a self,
+        rp: &mut RenderPass<'a>,
+        world: &'a World,
+        frustum: &Frustum,
+        wireframe: bool,
+        camera_bind_group: &'a BindGroup,
+        light_bind_group: &'a BindGroup,
+    ) {
+        if wireframe {
+            rp.set_pipeline(&self.wireframe_render_pipeline);
+        } else {
+            rp.set_pipeline(&self.render_pipeline);
+        }
+        rp.set_bind_group(0, &self.block_texture_bind_group, &[]);
+        rp.set_bind_group(1, camera_bind_group, &[]);
+        rp.set_bind_group(2, light_bind_group, &[]);
 
-        todo!()
+        for (_, (pos, mesh)) in world.query::<(&ChunkPos, &ChunkMesh)>() {
+            let (x, y, z) = pos.origin();
+            let min = Vector3::new(x as f32, y as f32, z as f32);
+            let max = min + Vector3::new(CHUNK_SIZE as f32, CHUNK_SIZE as f32, CHUNK_SIZE as f32);
+            if !frustum.intersects_aabb(min, max) {
+                continue;
+            }
+
+            rp.set_vertex_buffer(0, mesh.vertex_buffer.slice(..));
+            rp.set_index_buffer(mesh.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+            rp.draw_indexed(0..mesh.num_indices, 0, 0..1);
+        }
+    }
+}
+
+/// Noise for the texel at `x`, `y` of `layer`, the same on every run so
+/// frames rendered from the same world match.
+fn texel_jitter(layer: u32, x: u32, y: u32) -> u8 {
+    let mut hash = (layer * BLOCK_TEXTURE_SIZE + y) * BLOCK_TEXTURE_SIZE + x;
+    // lowbias32 integer hash.
+    hash ^= hash >> 16;
+    hash = hash.wrapping_mul(0x7feb_352d);
+    hash ^= hash >> 15;
+    hash = hash.wrapping_mul(0x846c_a68b);
+    hash ^= hash >> 16;
+    hash as u8
+}
+
+/// One layer per block texture, indexed by `Block::texture_layer`.
+fn create_block_texture_array(device: &Device, queue: &Queue) -> wgpu::TextureView {
+    let size = wgpu::Extent3d {
+        width: BLOCK_TEXTURE_SIZE,
+        height: BLOCK_TEXTURE_SIZE,
+        depth_or_array_layers: BLOCK_TEXTURE_LAYERS,
+    };
+    let texture = device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("voxel_block_textures"),
+        size,
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: wgpu::TextureFormat::Rgba8UnormSrgb,
+        usage: wgpu::TextureUsages::TEXTURE_BINDING | wgpu::TextureUsages::COPY_DST,
+        view_formats: &[],
+    });
+
+    let mut rgba = Vec::with_capacity((BLOCK_TEXTURE_SIZE * BLOCK_TEXTURE_SIZE * 4) as usize);
+    for layer in 0..BLOCK_TEXTURE_LAYERS {
+        for y in 0..BLOCK_TEXTURE_SIZE {
+            for x in 0..BLOCK_TEXTURE_SIZE {
+                let (color, alpha) = match layer {
+                    0 => ([128, 128, 128], 255),
+                    1 => ([134, 96, 67], 255),
+                    2 => ([95, 159, 53], 255),
+                    3 if y < 3 => ([95, 159, 53], 255),
+                    3 => ([134, 96, 67], 255),
+                    _ => ([50, 100, 200], 200),
+                };
+                let jitter = texel_jitter(layer, x, y) % 24;
+                rgba.extend(color.map(|c: u8| c.saturating_add(jitter).saturating_sub(12)));
+                rgba.push(alpha);
+            }
+        }
     }
 
-    pub fn render(&mut self) {}
+    queue.write_texture(
+        texture.as_image_copy(),
+        &rgba,
+        wgpu::ImageDataLayout {
+            offset: 0,
+            bytes_per_row: Some(4 * BLOCK_TEXTURE_SIZE),
+            rows_per_image: Some(BLOCK_TEXTURE_SIZE),
+        },
+        size,
+    );
+
+    texture.create_view(&wgpu::TextureViewDescriptor {
+        dimension: Some(wgpu::TextureViewDimension::D2Array),
+        ..Default::default()
+    })
 }
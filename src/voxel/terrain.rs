@@ -0,0 +1,227 @@
+use std::path::Path;
+
+use anyhow::Context;
+use log::info;
+
+use crate::noise::{
+    cpu::{fractal, perlin_noise2},
+    FractalMode, FractalSettings,
+};
+
+use super::{
+    block::Block,
+    chunk::{Chunk, ChunkPos, CHUNK_SIZE},
+    world::VoxelWorld,
+};
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct TerrainConfig {
+    pub seed: f32,
+    /// Noise frequency per block.
+    pub frequency: f32,
+    /// Octaves layered on top of the base frequency.
+    pub fractal: FractalSettings,
+    /// Surface height where the noise is zero.
+    pub base_height: i32,
+    /// Surface height change for a noise value of one.
+    pub height_scale: f32,
+    /// Columns below this height are flooded up to it.
+    pub water_level: i32,
+    /// Layers of dirt between the surface and the stone.
+    pub dirt_depth: i32,
+}
+
+impl Default for TerrainConfig {
+    fn default() -> Self {
+        Self {
+            seed: 0.0,
+            frequency: 0.02,
+            fractal: FractalSettings {
+                mode: FractalMode::Fbm,
+                octaves: 4,
+                lacunarity: 2.0,
+                persistence: 0.5,
+                amplitude: 1.0,
+            },
+            base_height: 0,
+            height_scale: 16.0,
+            water_level: -2,
+            dirt_depth: 3,
+        }
+    }
+}
+
+impl TerrainConfig {
+    /// Reads the world file at `path`. A missing file is created with a
+    /// random seed, so the same path always launches into the same world.
+    pub fn load_or_create(path: &Path) -> anyhow::Result<Self> {
+        match std::fs::read_to_string(path) {
+            Ok(json) => {
+                serde_json::from_str(&json).with_context(|| format!("Failed to parse {path:?}"))
+            }
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                let config = Self {
+                    seed: rand::random::<f32>() * 10000.0,
+                    ..Default::default()
+                };
+                config.save(path)?;
+                info!("Created world {:?}", path);
+                Ok(config)
+            }
+            Err(err) => Err(err).with_context(|| format!("Failed to read {path:?}")),
+        }
+    }
+
+    pub fn save(&self, path: &Path) -> anyhow::Result<()> {
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write {path:?}"))?;
+
+        Ok(())
+    }
+}
+
+pub struct TerrainGenerator {
+    config: TerrainConfig,
+    offset: (f32, f32),
+}
+
+impl TerrainGenerator {
+    pub fn new(config: TerrainConfig) -> Self {
+        // The noise repeats every 289 units, so the seed picks where in that
+        // period the world is sampled.
+        let fract = |x: f32| x - x.floor();
+        let offset = (
+            fract(config.seed * 0.618_034) * 289.0,
+            fract(config.seed * 0.414_213_6) * 289.0,
+        );
+
+        Self { config, offset }
+    }
+
+    pub fn config(&self) -> &TerrainConfig {
+        &self.config
+    }
+
+    pub fn height_at(&self, x: i32, z: i32) -> i32 {
+        let x = x as f32 * self.config.frequency + self.offset.0;
+        let z = z as f32 * self.config.frequency + self.offset.1;
+        let noise = fractal(&self.config.fractal, |frequency| {
+            perlin_noise2(x * frequency, z * frequency)
+        });
+
+        self.config.base_height + (noise * self.config.height_scale).round() as i32
+    }
+
+    pub fn block_at(&self, y: i32, height: i32) -> Block {
+        let config = &self.config;
+        if y > height {
+            if y <= config.water_level {
+                Block::Water
+            } else {
+                Block::Air
+            }
+        } else if y == height && height >= config.water_level {
+            Block::Grass
+        } else if y > height - config.dirt_depth {
+            Block::Dirt
+        } else {
+            Block::Stone
+        }
+    }
+
+    /// Generates the chunk at `pos`, `None` if it would be all air.
+    pub fn generate_chunk(&self, pos: ChunkPos) -> Option<Chunk> {
+        let (ox, oy, oz) = pos.origin();
+        let mut heights = [0; CHUNK_SIZE * CHUNK_SIZE];
+        for z in 0..CHUNK_SIZE {
+            for x in 0..CHUNK_SIZE {
+                heights[x + z * CHUNK_SIZE] = self.height_at(ox + x as i32, oz + z as i32);
+            }
+        }
+
+        let highest = heights.iter().copied().max().unwrap_or_default();
+        if oy > highest.max(self.config.water_level) {
+            return None;
+        }
+
+        let mut chunk = Chunk::default();
+        for y in 0..CHUNK_SIZE {
+            for z in 0..CHUNK_SIZE {
+                for x in 0..CHUNK_SIZE {
+                    let block = self.block_at(oy + y as i32, heights[x + z * CHUNK_SIZE]);
+                    chunk.set(x, y, z, block);
+                }
+            }
+        }
+
+        Some(chunk)
+    }
+
+    /// Fills the chunks within `radius` chunk columns of `center`, between
+    /// chunk heights `min_y..=max_y`.
+    pub fn generate_area(
+        &self,
+        world: &mut VoxelWorld,
+        center: ChunkPos,
+        radius: i32,
+        min_y: i32,
+        max_y: i32,
+    ) {
+        for cz in center.z - radius..=center.z + radius {
+            for cx in center.x - radius..=center.x + radius {
+                for cy in min_y..=max_y {
+                    let pos = ChunkPos::new(cx, cy, cz);
+                    match self.generate_chunk(pos) {
+                        Some(chunk) => {
+                            world.insert_chunk(pos, chunk);
+                        }
+                        None => {
+                            world.remove_chunk(pos);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn generate(seed: f32) -> VoxelWorld {
+        let generator = TerrainGenerator::new(TerrainConfig {
+            seed,
+            ..Default::default()
+        });
+        let mut world = VoxelWorld::default();
+        generator.generate_area(&mut world, ChunkPos::new(0, 0, 0), 2, -1, 1);
+        world
+    }
+
+    fn same_blocks(a: &VoxelWorld, b: &VoxelWorld) -> bool {
+        a.chunk_count() == b.chunk_count()
+            && a.chunks().all(|(pos, chunk)| {
+                let Some(other) = b.chunk(*pos) else {
+                    return false;
+                };
+                (0..CHUNK_SIZE).all(|y| {
+                    (0..CHUNK_SIZE)
+                        .all(|z| (0..CHUNK_SIZE).all(|x| chunk.get(x, y, z) == other.get(x, y, z)))
+                })
+            })
+    }
+
+    #[test]
+    fn same_seed_gives_same_world() {
+        assert!(same_blocks(&generate(1234.5), &generate(1234.5)));
+    }
+
+    #[test]
+    fn different_seeds_give_different_worlds() {
+        assert!(!same_blocks(&generate(1234.5), &generate(42.0)));
+    }
+}
@@ -45,7 +45,7 @@ pub struct PrimitiveRenderer {
     instance_buffer: wgpu::Buffer,
     num_elements: u32,
     num_instances: u32,
-    instances: Vec<Instance>,
+    pub instances: Vec<Instance>,
 }
 
 impl PrimitiveRenderer {
@@ -71,7 +71,7 @@ impl PrimitiveRenderer {
         let instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Instance Buffer"),
             contents: bytemuck::cast_slice(&instance_data),
-            usage: wgpu::BufferUsages::VERTEX,
+            usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
         });
         let num_elements = indices.len() as _;
         let num_instances = instances.len() as _;
@@ -86,18 +86,24 @@ impl PrimitiveRenderer {
         }
     }
 
-    pub fn add_instance(&mut self, instance: Instance, queue: &Queue) {
+    pub fn add_instance(&mut self, instance: Instance, device: &Device, queue: &Queue) -> usize {
         self.instances.push(instance);
-        self.update_instances(queue);
+        self.update_instances(device, queue);
+        self.instances.len() - 1
     }
 
-    pub fn update_instances(&mut self, queue: &Queue) {
+    pub fn update_instances(&mut self, device: &Device, queue: &Queue) {
         let instance_data: Vec<_> = self.instances.iter().map(Instance::to_raw).collect();
-        queue.write_buffer(
-            &self.instance_buffer,
-            0,
-            bytemuck::cast_slice(&instance_data),
-        );
+        let contents: &[u8] = bytemuck::cast_slice(&instance_data);
+        if contents.len() as u64 > self.instance_buffer.size() {
+            self.instance_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Instance Buffer"),
+                contents,
+                usage: wgpu::BufferUsages::VERTEX | wgpu::BufferUsages::COPY_DST,
+            });
+        } else {
+            queue.write_buffer(&self.instance_buffer, 0, contents);
+        }
         self.num_instances = self.instances.len() as _;
     }
 
@@ -149,11 +155,17 @@ pub struct TriangleRenderer {
 pub struct ModelVertex {
     pub position: [f32; 3],
     pub tex_coords: [f32; 2],
+    pub normal: [f32; 3],
+    pub layer: u32,
 }
 
 impl ModelVertex {
-    const ATTRIBS: [wgpu::VertexAttribute; 2] =
-        wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x2, ];
+    const ATTRIBS: [wgpu::VertexAttribute; 4] = wgpu::vertex_attr_array![
+        0 => Float32x3,
+        1 => Float32x2,
+        2 => Float32x3,
+        3 => Uint32,
+    ];
 }
 
 impl Vertex for ModelVertex {
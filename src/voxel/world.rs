@@ -0,0 +1,189 @@
+use std::collections::{HashMap, HashSet};
+
+use super::{
+    block::Block,
+    chunk::{Chunk, ChunkPos, CHUNK_SIZE},
+};
+
+#[derive(Debug, Default)]
+pub struct VoxelWorld {
+    chunks: HashMap<ChunkPos, Chunk>,
+    dirty: HashSet<ChunkPos>,
+}
+
+impl VoxelWorld {
+    pub fn chunk(&self, pos: ChunkPos) -> Option<&Chunk> {
+        self.chunks.get(&pos)
+    }
+
+    pub fn insert_chunk(&mut self, pos: ChunkPos, chunk: Chunk) -> Option<Chunk> {
+        self.mark_dirty_with_neighbours(pos);
+        self.chunks.insert(pos, chunk)
+    }
+
+    pub fn remove_chunk(&mut self, pos: ChunkPos) -> Option<Chunk> {
+        let chunk = self.chunks.remove(&pos)?;
+        self.mark_dirty_with_neighbours(pos);
+        Some(chunk)
+    }
+
+    pub fn chunks(&self) -> impl Iterator<Item = (&ChunkPos, &Chunk)> {
+        self.chunks.iter()
+    }
+
+    pub fn chunk_count(&self) -> usize {
+        self.chunks.len()
+    }
+
+    /// Block at world coordinates, unloaded chunks read as air.
+    pub fn get_block(&self, x: i32, y: i32, z: i32) -> Block {
+        let (pos, (lx, ly, lz)) = ChunkPos::from_world(x, y, z);
+        self.chunks
+            .get(&pos)
+            .map(|chunk| chunk.get(lx, ly, lz))
+            .unwrap_or_default()
+    }
+
+    /// Sets the block at world coordinates and returns the block it replaced.
+    /// Missing chunks are created on demand, except when writing air.
+    pub fn set_block(&mut self, x: i32, y: i32, z: i32, block: Block) -> Block {
+        let (pos, (lx, ly, lz)) = ChunkPos::from_world(x, y, z);
+        let old_block = match self.chunks.get_mut(&pos) {
+            Some(chunk) => chunk.set(lx, ly, lz, block),
+            None if block.is_air() => return Block::Air,
+            None => {
+                self.mark_dirty_with_neighbours(pos);
+                self.chunks.entry(pos).or_default().set(lx, ly, lz, block)
+            }
+        };
+
+        if old_block != block {
+            self.dirty.insert(pos);
+            // Blocks on the border also change which faces the neighbour shows.
+            for (axis, local) in [lx, ly, lz].into_iter().enumerate() {
+                let step = match local {
+                    0 => -1,
+                    l if l == CHUNK_SIZE - 1 => 1,
+                    _ => continue,
+                };
+                let mut offset = [0; 3];
+                offset[axis] = step;
+                self.dirty
+                    .insert(pos.offset(offset[0], offset[1], offset[2]));
+            }
+        }
+
+        old_block
+    }
+
+    pub fn has_dirty(&self) -> bool {
+        !self.dirty.is_empty()
+    }
+
+    /// Up to `limit` chunks whose mesh is out of date, the rest stay queued.
+    pub fn take_dirty(&mut self, limit: usize) -> Vec<ChunkPos> {
+        let taken: Vec<_> = self.dirty.iter().take(limit).copied().collect();
+        for pos in taken.iter() {
+            self.dirty.remove(pos);
+        }
+
+        taken
+    }
+
+    fn mark_dirty_with_neighbours(&mut self, pos: ChunkPos) {
+        self.dirty.insert(pos);
+        for (dx, dy, dz) in [
+            (-1, 0, 0),
+            (1, 0, 0),
+            (0, -1, 0),
+            (0, 1, 0),
+            (0, 0, -1),
+            (0, 0, 1),
+        ] {
+            self.dirty.insert(pos.offset(dx, dy, dz));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dirty(world: &mut VoxelWorld) -> HashSet<ChunkPos> {
+        world.take_dirty(usize::MAX).into_iter().collect()
+    }
+
+    #[test]
+    fn blocks_land_in_the_chunk_containing_them() {
+        let mut world = VoxelWorld::default();
+        assert_eq!(world.set_block(-1, 0, 0, Block::Stone), Block::Air);
+        assert_eq!(world.set_block(0, 0, 0, Block::Dirt), Block::Air);
+        assert_eq!(world.set_block(0, -1, 32, Block::Water), Block::Air);
+
+        assert_eq!(world.get_block(-1, 0, 0), Block::Stone);
+        assert_eq!(world.get_block(0, 0, 0), Block::Dirt);
+        assert_eq!(world.get_block(0, -1, 32), Block::Water);
+        assert_eq!(world.get_block(-2, 0, 0), Block::Air);
+        assert_eq!(world.chunk_count(), 3);
+        assert_eq!(
+            world.chunk(ChunkPos::new(-1, 0, 0)).unwrap().get(31, 0, 0),
+            Block::Stone
+        );
+        assert_eq!(
+            world.chunk(ChunkPos::new(0, -1, 1)).unwrap().get(0, 31, 0),
+            Block::Water
+        );
+    }
+
+    #[test]
+    fn writing_air_doesnt_create_chunks() {
+        let mut world = VoxelWorld::default();
+        assert_eq!(world.set_block(100, 100, 100, Block::Air), Block::Air);
+        assert_eq!(world.chunk_count(), 0);
+        assert!(!world.has_dirty());
+    }
+
+    #[test]
+    fn border_blocks_dirty_their_neighbours() {
+        let mut world = VoxelWorld::default();
+        world.set_block(5, 5, 5, Block::Stone);
+        dirty(&mut world);
+
+        world.set_block(6, 5, 5, Block::Stone);
+        assert_eq!(dirty(&mut world), HashSet::from([ChunkPos::new(0, 0, 0)]));
+
+        world.set_block(31, 0, 5, Block::Stone);
+        assert_eq!(
+            dirty(&mut world),
+            HashSet::from([
+                ChunkPos::new(0, 0, 0),
+                ChunkPos::new(1, 0, 0),
+                ChunkPos::new(0, -1, 0),
+            ])
+        );
+
+        // Nothing changed, so nothing needs meshing.
+        world.set_block(31, 0, 5, Block::Stone);
+        assert!(!world.has_dirty());
+    }
+
+    #[test]
+    fn new_chunks_dirty_every_neighbour() {
+        let mut world = VoxelWorld::default();
+        world.set_block(-1, -1, -1, Block::Grass);
+        let dirty = dirty(&mut world);
+        assert_eq!(dirty.len(), 7);
+        assert!(dirty.contains(&ChunkPos::new(-1, -1, -1)));
+        assert!(dirty.contains(&ChunkPos::new(0, -1, -1)));
+        assert!(dirty.contains(&ChunkPos::new(-1, -2, -1)));
+    }
+
+    #[test]
+    fn take_dirty_leaves_the_rest_queued() {
+        let mut world = VoxelWorld::default();
+        world.set_block(0, 0, 0, Block::Stone);
+        assert_eq!(world.take_dirty(3).len(), 3);
+        assert_eq!(world.take_dirty(10).len(), 4);
+        assert!(!world.has_dirty());
+    }
+}